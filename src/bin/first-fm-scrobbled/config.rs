@@ -0,0 +1,158 @@
+use std::env;
+use std::fs;
+use std::io::ErrorKind as IoErrorKind;
+use std::path::{Path, PathBuf};
+
+use first_fm::{Error, Result, Service};
+use first_fm::mpd::MPD_DEFAULT_ADDRESS;
+use first_fm::session::Session;
+
+// ----------------------------------------------------------------
+
+/// Daemon configuration
+///
+/// Loaded from a `name = value` file, lines starting with `#` are comments
+/// (`#` elsewhere is a part of the value, secrets and passwords may contain it):
+///
+/// ```text
+/// api_key = xxxxxxxx
+/// secret = yyyyyyyy
+/// # optional, defaults are shown
+/// # lastfm, librefm, librefm-legacy, gnufm:<root url> or audioscrobbler:<handshake url>
+/// service = lastfm
+/// session = ~/.config/first-fm/session
+/// cache = ~/.cache/first-fm/scrobbles
+/// # session can be repeated to scrobble to several accounts,
+/// # each one gets own cache file: `<cache>.<n>`
+/// session = ~/.config/first-fm/session.work
+/// mpd = localhost:6600
+/// mpd_password = zzzzzzzz
+/// # optional track tag normalizer rules, see `first_fm::normalize::Normalizer`
+/// normalize = ~/.config/first-fm/normalize.rules
+/// ```
+#[derive(Debug)]
+pub struct Config {
+    pub api_key: String,
+    pub secret: String,
+    pub service: Service,
+    pub sessions: Vec<PathBuf>,
+    pub cache: PathBuf,
+    pub mpd: String,
+    pub mpd_password: Option<String>,
+    pub normalize: Option<PathBuf>,
+}
+
+fn invalid(message: String) -> Error {
+    Error::io(IoErrorKind::InvalidData, message)
+}
+
+fn home() -> Option<PathBuf> {
+    env::var_os("HOME").map(PathBuf::from)
+}
+
+/// Expands leading `~/` to home directory
+fn expand(path: &str) -> PathBuf {
+    match (path.strip_prefix("~/"), home()) {
+        (Some(rest), Some(home)) => home.join(rest),
+        _ => PathBuf::from(path),
+    }
+}
+
+impl Config {
+    /// Cache file of n-th session, the first one uses `cache` as is
+    pub fn cache_of(&self, n: usize) -> PathBuf {
+        match n {
+            0 => self.cache.clone(),
+            n => {
+                let mut name = self.cache.clone().into_os_string();
+                name.push(format!(".{}", n));
+                PathBuf::from(name)
+            }
+        }
+    }
+
+    /// Default config file location: `$XDG_CONFIG_HOME/first-fm/scrobbled.conf` or `~/.config/first-fm/scrobbled.conf`
+    pub fn default_path() -> Option<PathBuf> {
+        let config = env::var_os("XDG_CONFIG_HOME")
+            .map(PathBuf::from)
+            .or_else(|| home().map(|h| h.join(".config")))?;
+        Some(config.join("first-fm").join("scrobbled.conf"))
+    }
+
+    fn default_cache() -> Option<PathBuf> {
+        let cache = env::var_os("XDG_CACHE_HOME")
+            .map(PathBuf::from)
+            .or_else(|| home().map(|h| h.join(".cache")))?;
+        Some(cache.join("first-fm").join("scrobbles"))
+    }
+
+    /// Loads configuration from given file
+    pub fn load(path: &Path) -> Result<Config> {
+        let contents = fs::read_to_string(path)
+            .map_err(|e| invalid(format!("Can't read config {}: {}", path.display(), e)))?;
+
+        let (mut api_key, mut secret, mut cache, mut mpd, mut mpd_password, mut normalize) =
+            (None, None, None, None, None, None);
+        let mut service = Service::Lastfm;
+        let mut sessions = Vec::new();
+
+        for (n, line) in contents.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let eq = line
+                .find('=')
+                .ok_or_else(|| invalid(format!("{}:{}: expected name = value", path.display(), n + 1)))?;
+            let (name, value) = (line[..eq].trim(), line[eq + 1..].trim().to_owned());
+
+            match name {
+                "api_key" => api_key = Some(value),
+                "secret" => secret = Some(value),
+                "service" => {
+                    service = value
+                        .parse()
+                        .map_err(|e| invalid(format!("{}:{}: {}", path.display(), n + 1, e)))?
+                }
+                "session" => sessions.push(expand(&value)),
+                "cache" => cache = Some(expand(&value)),
+                "mpd" => mpd = Some(value),
+                "mpd_password" => mpd_password = Some(value),
+                "normalize" => normalize = Some(expand(&value)),
+                other => return Err(invalid(format!("{}:{}: unknown setting {}", path.display(), n + 1, other))),
+            }
+        }
+
+        let missing = |name: &str| invalid(format!("{}: {} is not set", path.display(), name));
+        if sessions.is_empty() {
+            sessions.push(Session::default_path().ok_or_else(|| missing("session"))?);
+        }
+        Ok(Config {
+            api_key: api_key.ok_or_else(|| missing("api_key"))?,
+            secret: secret.ok_or_else(|| missing("secret"))?,
+            service,
+            sessions,
+            cache: cache.or_else(Config::default_cache).ok_or_else(|| missing("cache"))?,
+            mpd: mpd.unwrap_or_else(|| MPD_DEFAULT_ADDRESS.to_owned()),
+            mpd_password,
+            normalize,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn hash_in_values() {
+        let path = env::temp_dir().join(format!("first-fm-scrobbled-{}.conf", std::process::id()));
+        fs::write(&path, "# comment\napi_key = key\n  # indented comment\nsecret = ab#cd\nmpd_password = #zz\n").unwrap();
+
+        let config = Config::load(&path).unwrap();
+        fs::remove_file(&path).unwrap();
+        assert_eq!(config.api_key, "key");
+        assert_eq!(config.secret, "ab#cd");
+        assert_eq!(config.mpd_password.as_deref(), Some("#zz"));
+    }
+}
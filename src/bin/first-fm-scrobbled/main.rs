@@ -0,0 +1,204 @@
+//! `first-fm-scrobbled` scrobbling daemon.
+//!
+//! Follows local MPD server and scrobbles what it plays.
+//! Session is taken from the session file written by `first-fm auth`,
+//! tracks that couldn't be submitted are kept in the cache file between runs.
+//! Several sessions can be configured, every one of them scrobbles independently.
+//! Revoked session is picked up again once `first-fm auth` renews its session file.
+//!
+//! usage: first-fm-scrobbled [config file]
+
+mod config;
+
+use std::env;
+use std::path::{Path, PathBuf};
+use std::process;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use signal_hook::consts::{SIGINT, SIGTERM};
+
+use first_fm::{Client, Error, Result};
+use first_fm::group::Group;
+use first_fm::mpd::{Mpd, MpdSource};
+use first_fm::normalize::Normalizer;
+use first_fm::player::PlayerEvent;
+use first_fm::scrobbler::{Driver, Scrobbler};
+use first_fm::session::Session;
+
+use crate::config::Config;
+
+// ----------------------------------------------------------------
+
+/// How often shutdown flag is checked
+static SHUTDOWN_POLL_MS: u64 = 250;
+
+/// Delay before reconnecting to MPD
+static RECONNECT_DELAY_SEC: u64 = 5;
+
+/// Time given to submit and save pending scrobbles on exit
+static SHUTDOWN_DEADLINE_SEC: u64 = 10;
+
+/// How often session files of revoked sessions are checked for renewed keys
+static SESSION_RELOAD_SEC: u64 = 5;
+
+/// Client of a configured session, along with the file its session is loaded from
+struct Account {
+    name: String,
+    session: PathBuf,
+    client: Client,
+}
+
+fn main() {
+    if let Err(e) = run() {
+        eprintln!("first-fm-scrobbled: {}", e);
+        process::exit(1);
+    }
+}
+
+fn run() -> Result<()> {
+    let path = env::args_os()
+        .nth(1)
+        .map(PathBuf::from)
+        .or_else(Config::default_path)
+        .ok_or_else(|| Error::build("Can't locate config file, pass it as an argument"))?;
+    let config = Config::load(&path)?;
+
+    let shutdown = Arc::new(AtomicBool::new(false));
+    for &signal in &[SIGINT, SIGTERM] {
+        signal_hook::flag::register(signal, shutdown.clone())?;
+    }
+
+    let mut group = Group::new();
+    let mut accounts = Vec::new();
+    for (n, path) in config.sessions.iter().enumerate() {
+        let (account, scrobbler) = build_scrobbler(&config, path, &config.cache_of(n))?;
+        eprintln!("Scrobbling as {}, {} cached scrobbles", account.name, scrobbler.pending_count());
+        group = group.add(account.name.clone(), scrobbler)?;
+        accounts.push(account);
+    }
+    eprintln!("Following MPD at {}", config.mpd);
+
+    // watcher thread is blocked in MPD idle most of the time, so it's left behind on exit
+    let driver = group.driver();
+    let (address, password) = (config.mpd.clone(), config.mpd_password.clone());
+    thread::spawn(move || watch(&address, password.as_deref(), driver));
+
+    let mut reported = Vec::new();
+    let mut reloaded = Instant::now();
+    while !shutdown.load(Ordering::SeqCst) {
+        thread::sleep(Duration::from_millis(SHUTDOWN_POLL_MS));
+
+        let needs_auth: Vec<String> = group.needs_auth().into_iter().map(str::to_owned).collect();
+        for name in needs_auth.iter().filter(|n| !reported.contains(*n)) {
+            eprintln!("Session of {} is not valid, run `first-fm auth` to renew it", name);
+        }
+        reported = needs_auth;
+
+        if !reported.is_empty() && reloaded.elapsed() >= Duration::from_secs(SESSION_RELOAD_SEC) {
+            reloaded = Instant::now();
+            for account in accounts.iter().filter(|a| reported.contains(&a.name)) {
+                reload_session(account, &group);
+            }
+        }
+    }
+
+    eprintln!("Shutting down");
+    for (name, report) in group.shutdown(Duration::from_secs(SHUTDOWN_DEADLINE_SEC)) {
+        eprintln!("Submitted {} scrobbles as {}", report.submitted.len(), name);
+        if !report.pending.is_empty() {
+            match report.saved_to {
+                Some(ref path) => eprintln!("Saved {} scrobbles to {}", report.pending.len(), path.display()),
+                None => eprintln!("Failed to save {} scrobbles of {}", report.pending.len(), name),
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Builds scrobbler for given session file, returns it along with its account
+fn build_scrobbler(config: &Config, path: &Path, cache: &Path) -> Result<(Account, Scrobbler)> {
+    let session = Session::load(path)?.ok_or_else(|| {
+        Error::build(format!("No session in {}, run `first-fm auth` first", path.display()))
+    })?;
+
+    let mut builder = Client::builder()
+        .service(config.service.clone())
+        .api_key(&config.api_key)
+        .secret(&config.secret)
+        .session_key(&session.key);
+    if let Some(ref user) = session.user {
+        builder = builder.user(user);
+    }
+    let client = builder.build()?;
+
+    let mut builder = Scrobbler::builder(&client).cache_file(cache);
+    // scrobbles cut off by a network failure may have made it, look them up before resubmitting
+    if let Some(ref user) = session.user {
+        builder = builder.verify_recent_tracks(user);
+    }
+    if let Some(ref rules) = config.normalize {
+        builder = builder.normalizer(Normalizer::load(rules)?);
+    }
+
+    let account = Account {
+        name: session.user.unwrap_or_else(|| cache.display().to_string()),
+        session: path.to_owned(),
+        client,
+    };
+    Ok((account, builder.build()?))
+}
+
+/// Picks up renewed session key from account's session file and resubmits its scrobbles
+fn reload_session(account: &Account, group: &Group) {
+    let key = match Session::load(&account.session) {
+        Ok(Some(session)) => session.key,
+        Ok(None) => return,
+        Err(e) => {
+            eprintln!("Can't reload session of {}: {}", account.name, e);
+            return;
+        }
+    };
+    if account.client.session_key().as_deref() == Some(key.as_str()) {
+        return;
+    }
+
+    account.client.set_session_key(&key);
+    let scrobbler = match group.get(&account.name) {
+        Some(scrobbler) => scrobbler,
+        None => return,
+    };
+    match futures::executor::block_on(scrobbler.flush()) {
+        Ok(submitted) => eprintln!("Session of {} is renewed, submitted {} scrobbles", account.name, submitted.len()),
+        Err(e) => eprintln!("Session of {} is renewed, but submission failed: {}", account.name, e),
+    }
+}
+
+// ----------------------------------------------------------------
+
+/// Follows MPD player, reconnecting on failures, until the scrobbler is gone
+fn watch(address: &str, password: Option<&str>, driver: Driver) {
+    loop {
+        match connect(address, password) {
+            Ok(mpd) => driver.run(&mut MpdSource::new(mpd)),
+            Err(e) => eprintln!("Can't connect to MPD: {}", e),
+        }
+
+        // nothing is known to be playing while disconnected
+        if !driver.handle(PlayerEvent::Stop) {
+            return;
+        }
+        eprintln!("Lost MPD connection, reconnecting in {}s", RECONNECT_DELAY_SEC);
+        thread::sleep(Duration::from_secs(RECONNECT_DELAY_SEC));
+    }
+}
+
+fn connect(address: &str, password: Option<&str>) -> Result<Mpd> {
+    let mut mpd = Mpd::connect(address)?;
+    if let Some(password) = password {
+        mpd.password(password)?;
+    }
+    Ok(mpd)
+}
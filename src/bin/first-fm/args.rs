@@ -0,0 +1,95 @@
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::io::ErrorKind as IoErrorKind;
+use std::str::FromStr;
+
+use first_fm::{Error, Result};
+
+// ----------------------------------------------------------------
+
+/// Options that don't take a value
+static FLAGS: &[&str] = &["json", "help", "unlove", "remove", "signed"];
+
+/// Returns usage error
+pub fn usage(message: &str) -> Error {
+    Error::io(IoErrorKind::InvalidInput, message.to_owned())
+}
+
+/// Minimal command line parser.
+///
+/// Splits arguments into positional ones, `--name value` (or `--name=value`) options
+/// and `--flag`s. Everything after `--` is positional.
+pub struct Args {
+    positional: VecDeque<String>,
+    options: HashMap<String, String>,
+    flags: HashSet<String>,
+}
+
+impl Args {
+    /// Parses given arguments (without program name)
+    pub fn parse<I: IntoIterator<Item = String>>(args: I) -> Result<Args> {
+        let mut parsed = Args {
+            positional: VecDeque::new(),
+            options: HashMap::new(),
+            flags: HashSet::new(),
+        };
+
+        let mut args = args.into_iter();
+        while let Some(arg) = args.next() {
+            if arg == "--" {
+                parsed.positional.extend(args.by_ref());
+                break;
+            }
+            if !arg.starts_with("--") {
+                parsed.positional.push_back(arg);
+                continue;
+            }
+
+            let arg = &arg[2..];
+            if let Some(eq) = arg.find('=') {
+                parsed.options.insert(arg[..eq].to_owned(), arg[eq + 1..].to_owned());
+            } else if FLAGS.contains(&arg) {
+                parsed.flags.insert(arg.to_owned());
+            } else {
+                let value = args.next().ok_or_else(|| usage(&format!("--{} needs a value", arg)))?;
+                parsed.options.insert(arg.to_owned(), value);
+            }
+        }
+        Ok(parsed)
+    }
+
+    /// Takes next positional argument
+    pub fn next(&mut self) -> Option<String> {
+        self.positional.pop_front()
+    }
+
+    /// Takes next positional argument, fails if there's none
+    pub fn required(&mut self, what: &str) -> Result<String> {
+        self.next().ok_or_else(|| usage(&format!("missing <{}>", what)))
+    }
+
+    /// Takes all remaining positional arguments
+    pub fn rest(&mut self) -> Vec<String> {
+        self.positional.drain(..).collect()
+    }
+
+    /// Returns value of given option
+    pub fn option(&self, name: &str) -> Option<&str> {
+        self.options.get(name).map(|v| v.as_str())
+    }
+
+    /// Returns parsed value of given option
+    pub fn parsed<T: FromStr>(&self, name: &str) -> Result<Option<T>> {
+        match self.option(name) {
+            Some(value) => value
+                .parse()
+                .map(Some)
+                .map_err(|_| usage(&format!("invalid --{} value: {}", name, value))),
+            None => Ok(None),
+        }
+    }
+
+    /// Checks whether given flag is set
+    pub fn flag(&self, name: &str) -> bool {
+        self.flags.contains(name)
+    }
+}
@@ -0,0 +1,369 @@
+//! `first-fm` command line client.
+//!
+//! API key and secret are taken from `--api-key`/`--secret` options or
+//! `FIRST_FM_API_KEY`/`FIRST_FM_SECRET` environment variables.
+//! Session obtained by `auth` is stored in `--session` file (`~/.config/first-fm/session` by default)
+//! and used by the rest of commands.
+
+mod args;
+mod output;
+
+use std::env;
+use std::io::{self, BufRead, Write};
+use std::path::PathBuf;
+use std::process;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use serde_json::{json, Value};
+
+use first_fm::{Client, Result, Service};
+use first_fm::blocking::Client as BlockingClient;
+use first_fm::lookup::Subject;
+use first_fm::normalize::Normalizer;
+use first_fm::scrobbler::Track;
+use first_fm::session::Session;
+use first_fm::write::Submission;
+
+use crate::args::{Args, usage};
+use crate::output::Format;
+
+// ----------------------------------------------------------------
+
+static USAGE: &str = "\
+usage: first-fm [options] <command> [arguments]
+
+commands:
+    auth mobile <username> <password>       authenticate with user credentials
+    auth desktop                            authenticate in the browser
+    now-playing <artist> <track>            update now playing track
+        [--album A] [--duration SEC]
+    scrobble <artist> <track>               scrobble a track played at given time (now by default)
+        [--album A] [--duration SEC] [--timestamp UNIX]
+    love <artist> <track> [--unlove]        love or unlove a track
+    tag artist <artist> <tag>...            add tags to an artist, album or track,
+    tag album <artist> <album> <tag>...     --remove removes them instead
+    tag track <artist> <track> <tag>...
+    recent [--user U] [--limit N]           recently played tracks
+    top <artists|albums|tracks>             top artists, albums or tracks
+        [--user U] [--period P] [--limit N]
+    raw <method> [name=value]... [--signed] call any API method
+    normalize <artist> <track>              show how track tags are normalized before scrobbling
+        [--album A] [--rules FILE]          (built-in rules are used without --rules)
+
+options:
+    --api-key KEY       API key (FIRST_FM_API_KEY)
+    --secret SECRET     API shared secret (FIRST_FM_SECRET)
+    --session FILE      session file (FIRST_FM_SESSION)
+    --service S         lastfm (default), librefm, librefm-legacy,
+                        gnufm:<root url> or audioscrobbler:<handshake url> (FIRST_FM_SERVICE)
+    --format F          output format: table (default) or json
+    --json              same as --format json
+";
+
+fn main() {
+    let res = Args::parse(env::args().skip(1)).and_then(|mut args| {
+        if args.flag("help") {
+            print!("{}", USAGE);
+            return Ok(());
+        }
+        run(&mut args)
+    });
+
+    if let Err(e) = res {
+        eprintln!("first-fm: {}", e);
+        eprintln!("try `first-fm --help` for usage");
+        process::exit(1);
+    }
+}
+
+// ----------------------------------------------------------------
+
+struct Context {
+    client: BlockingClient,
+    session: Option<Session>,
+    session_path: PathBuf,
+    format: Format,
+}
+
+impl Context {
+    fn new(args: &Args) -> Result<Context> {
+        let format = format(args)?;
+
+        let session_path = args
+            .option("session")
+            .map(PathBuf::from)
+            .or_else(|| env::var_os("FIRST_FM_SESSION").map(PathBuf::from))
+            .or_else(Session::default_path)
+            .ok_or_else(|| usage("can't locate session file, set --session"))?;
+        let session = Session::load(&session_path)?;
+
+        let api_key = setting(args, "api-key", "FIRST_FM_API_KEY")
+            .ok_or_else(|| usage("API key is not set, use --api-key or FIRST_FM_API_KEY"))?;
+
+        let user = session.as_ref().and_then(|s| s.user.clone());
+        let mut builder = Client::builder().api_key(&api_key);
+        if let Some(service) = setting(args, "service", "FIRST_FM_SERVICE") {
+            let service: Service = service.parse()?;
+            if service.handshake_url().is_some() && user.is_none() {
+                // legacy submission needs the user, who is only known after `auth`,
+                // 2.0 API of the service is enough to get there
+                if let Some(url) = service.base_url() {
+                    builder = builder.base_url(&url);
+                }
+                if let Some(url) = service.auth_url() {
+                    builder = builder.auth_url(&url);
+                }
+            } else {
+                builder = builder.service(service);
+            }
+        }
+        if let Some(secret) = setting(args, "secret", "FIRST_FM_SECRET") {
+            builder = builder.secret(&secret);
+        }
+        if let Some(ref session) = session {
+            builder = builder.session_key(&session.key);
+        }
+        if let Some(ref user) = user {
+            builder = builder.user(user);
+        }
+
+        Ok(Context {
+            client: BlockingClient::new(builder)?,
+            session,
+            session_path,
+            format,
+        })
+    }
+
+    /// User to query: given one or the one stored with the session
+    fn user(&self, args: &Args) -> Result<String> {
+        args.option("user")
+            .map(|u| u.to_owned())
+            .or_else(|| self.session.as_ref().and_then(|s| s.user.clone()))
+            .ok_or_else(|| usage("no user given and none is stored in session, use --user"))
+    }
+
+    fn print(&self, value: &Value, columns: &[&str]) {
+        output::print(self.format, value, columns);
+    }
+}
+
+fn format(args: &Args) -> Result<Format> {
+    match args.option("format") {
+        _ if args.flag("json") => Ok(Format::Json),
+        Some("json") => Ok(Format::Json),
+        Some("table") | None => Ok(Format::Table),
+        Some(other) => Err(usage(&format!("unknown format: {}", other))),
+    }
+}
+
+fn setting(args: &Args, option: &str, var: &str) -> Option<String> {
+    args.option(option)
+        .map(|v| v.to_owned())
+        .or_else(|| env::var(var).ok())
+}
+
+fn unix_now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or(0)
+}
+
+fn done() -> Value {
+    json!({ "status": "ok" })
+}
+
+// ----------------------------------------------------------------
+
+fn run(args: &mut Args) -> Result<()> {
+    let command = args.next().ok_or_else(|| usage("no command given"))?;
+    // works offline, doesn't need API key or session
+    if command == "normalize" {
+        return normalize(args);
+    }
+    let ctx = Context::new(args)?;
+
+    match command.as_str() {
+        "auth" => auth(&ctx, args),
+        "now-playing" => now_playing(&ctx, args),
+        "scrobble" => scrobble(&ctx, args),
+        "love" => love(&ctx, args),
+        "tag" => tag(&ctx, args),
+        "recent" => recent(&ctx, args),
+        "top" => top(&ctx, args),
+        "raw" => raw(&ctx, args),
+        other => Err(usage(&format!("unknown command: {}", other))),
+    }
+}
+
+fn auth(ctx: &Context, args: &mut Args) -> Result<()> {
+    let user = match args.required("mobile|desktop")?.as_str() {
+        "mobile" => {
+            let username = args.required("username")?;
+            let password = args.required("password")?;
+            ctx.client.mobile_auth(&username, &password)?;
+            username
+        }
+        "desktop" => {
+            let url = ctx.client.init_desktop_auth()?;
+            eprintln!("Open this url in the browser and allow access, then press Enter:\n{}", url);
+            io::stderr().flush()?;
+            io::stdin().lock().read_line(&mut String::new())?;
+            ctx.client.finalize_desktop_auth()?;
+
+            // user.getInfo without a user returns the authenticated one
+            let info = ctx.client.call_raw("user.getInfo", Vec::<(&str, &str)>::new(), true)?;
+            info["user"]["name"].as_str().unwrap_or_default().to_owned()
+        }
+        other => return Err(usage(&format!("unknown auth method: {}", other))),
+    };
+
+    let key = ctx.client.client().session_key().ok_or_else(|| usage("auth didn't return a session"))?;
+    Session::new(&key, Some(&user)).save(&ctx.session_path)?;
+
+    ctx.print(&json!({ "user": user, "session": ctx.session_path }), &[]);
+    Ok(())
+}
+
+fn track(args: &mut Args) -> Result<Track> {
+    let artist = args.required("artist")?;
+    let name = args.required("track")?;
+    let duration = args.parsed::<u32>("duration")?.unwrap_or(0);
+    let mut track = Track::new(&name, &artist, duration);
+    if let Some(album) = args.option("album") {
+        track = track.album(album);
+    }
+    Ok(track)
+}
+
+/// Output of a submission: tags the service corrected and the reason it ignored the track, if any
+fn submission(submission: &Submission) -> Value {
+    let correction = submission.correction.as_ref().map(|c| {
+        json!({ "artist": c.artist, "track": c.name, "album": c.album, "album_artist": c.album_artist })
+    });
+    json!({ "correction": correction, "ignored": submission.ignored })
+}
+
+fn now_playing(ctx: &Context, args: &mut Args) -> Result<()> {
+    let track = track(args)?;
+    let res = ctx.client.update_now_playing_submission(&track)?;
+    ctx.print(&submission(&res), &[]);
+    Ok(())
+}
+
+fn scrobble(ctx: &Context, args: &mut Args) -> Result<()> {
+    let timestamp = args.parsed::<u32>("timestamp")?.unwrap_or_else(|| unix_now() as u32);
+    let track = track(args)?.timestamp(timestamp);
+
+    let res = ctx.client.scrobble_submission(&[track])?;
+    let res: Vec<Value> = res.iter().map(submission).collect();
+    ctx.print(&Value::Array(res), &[]);
+    Ok(())
+}
+
+fn love(ctx: &Context, args: &mut Args) -> Result<()> {
+    let artist = args.required("artist")?;
+    let track = args.required("track")?;
+    if args.flag("unlove") {
+        ctx.client.unlove_track(&artist, &track)?;
+    } else {
+        ctx.client.love_track(&artist, &track)?;
+    }
+    ctx.print(&done(), &[]);
+    Ok(())
+}
+
+fn tag(ctx: &Context, args: &mut Args) -> Result<()> {
+    let subject = match args.required("artist|album|track")?.as_str() {
+        "artist" => Subject::Artist(args.required("artist")?),
+        "album" => Subject::Album(args.required("artist")?, args.required("album")?),
+        "track" => Subject::Track(args.required("artist")?, args.required("track")?),
+        other => return Err(usage(&format!("can't tag {}", other))),
+    };
+    let tags = args.rest();
+    if tags.is_empty() {
+        return Err(usage("missing <tag>"));
+    }
+
+    if args.flag("remove") {
+        for tag in &tags {
+            ctx.client.remove_tag(&subject, tag)?;
+        }
+    } else {
+        let tags: Vec<&str> = tags.iter().map(|t| t.as_str()).collect();
+        ctx.client.add_tags(&subject, &tags)?;
+    }
+    ctx.print(&done(), &[]);
+    Ok(())
+}
+
+fn recent(ctx: &Context, args: &mut Args) -> Result<()> {
+    let mut params = vec![("user".to_owned(), ctx.user(args)?)];
+    if let Some(limit) = args.parsed::<u32>("limit")? {
+        params.push(("limit".to_owned(), limit.to_string()));
+    }
+
+    let res = ctx.client.call_raw("user.getRecentTracks", params, false)?;
+    ctx.print(&res, &["artist", "name", "album", "date"]);
+    Ok(())
+}
+
+fn top(ctx: &Context, args: &mut Args) -> Result<()> {
+    let method = match args.required("artists|albums|tracks")?.as_str() {
+        "artists" => "user.getTopArtists",
+        "albums" => "user.getTopAlbums",
+        "tracks" => "user.getTopTracks",
+        other => return Err(usage(&format!("unknown top list: {}", other))),
+    };
+
+    let mut params = vec![("user".to_owned(), ctx.user(args)?)];
+    if let Some(period) = args.option("period") {
+        params.push(("period".to_owned(), period.to_owned()));
+    }
+    if let Some(limit) = args.parsed::<u32>("limit")? {
+        params.push(("limit".to_owned(), limit.to_string()));
+    }
+
+    let res = ctx.client.call_raw(method, params, false)?;
+    ctx.print(&res, &["@attr", "artist", "name", "playcount"]);
+    Ok(())
+}
+
+fn raw(ctx: &Context, args: &mut Args) -> Result<()> {
+    let method = args.required("method")?;
+    let params = args
+        .rest()
+        .into_iter()
+        .map(|param| match param.find('=') {
+            Some(eq) => Ok((param[..eq].to_owned(), param[eq + 1..].to_owned())),
+            None => Err(usage(&format!("expected name=value, got {}", param))),
+        })
+        .collect::<Result<Vec<_>>>()?;
+
+    let res = ctx.client.call_raw(&method, params, args.flag("signed"))?;
+    ctx.print(&res, &[]);
+    Ok(())
+}
+
+fn normalize(args: &mut Args) -> Result<()> {
+    let artist = args.required("artist")?;
+    let name = args.required("track")?;
+    let mut track = Track::new(&name, &artist, 0);
+    if let Some(album) = args.option("album") {
+        track = track.album(album);
+    }
+
+    let normalizer = match args.option("rules") {
+        Some(path) => Normalizer::load(path)?,
+        None => Normalizer::builtin(),
+    };
+    let audit = normalizer.audit(&track);
+
+    let tags = |tags: &str, track: &Track| {
+        json!({ "tags": tags, "artist": track.artist, "name": track.name, "album": track.album })
+    };
+    let res = json!([tags("original", &audit.original), tags("normalized", &audit.normalized)]);
+    output::print(format(args)?, &res, &["tags", "artist", "name", "album"]);
+    Ok(())
+}
@@ -0,0 +1,158 @@
+use serde_json::Value;
+
+// ----------------------------------------------------------------
+
+/// Max width of a table cell, longer values are cut
+static MAX_CELL_WIDTH: usize = 40;
+
+/// Output format
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Format {
+    /// Response as returned by last.fm, pretty-printed
+    Json,
+    /// Human-readable table
+    Table,
+}
+
+/// Prints response in given format.
+///
+/// Tables are built from the first list of objects found in the response
+/// (like `recenttracks.track`), only `columns` are shown if any of them are present.
+/// Responses without lists are printed as `key: value` pairs.
+pub fn print(format: Format, value: &Value, columns: &[&str]) {
+    match format {
+        Format::Json => println!("{}", serde_json::to_string_pretty(value).unwrap_or_default()),
+        Format::Table => match rows(value) {
+            Some(rows) => print_rows(rows, columns),
+            None => print_pairs(value),
+        },
+    }
+}
+
+fn rows(value: &Value) -> Option<&Vec<Value>> {
+    match *value {
+        Value::Array(ref items) if items.iter().any(|i| i.is_object()) => Some(items),
+        Value::Object(ref fields) => fields.values().find_map(rows),
+        _ => None,
+    }
+}
+
+/// Renders a field as a single line, nested objects are represented by their text or name
+fn cell(value: &Value) -> Option<String> {
+    let text = match *value {
+        Value::String(ref s) => s.clone(),
+        Value::Number(ref n) => n.to_string(),
+        Value::Bool(b) => b.to_string(),
+        Value::Object(ref fields) => {
+            return ["#text", "name", "rank"]
+                .iter()
+                .find_map(|key| fields.get(*key))
+                .and_then(cell);
+        }
+        _ => return None,
+    };
+
+    let text = text.replace('\n', " ");
+    Some(if text.chars().count() > MAX_CELL_WIDTH {
+        let cut: String = text.chars().take(MAX_CELL_WIDTH - 1).collect();
+        format!("{}…", cut)
+    } else {
+        text
+    })
+}
+
+fn print_rows(rows: &[Value], columns: &[&str]) {
+    let mut names: Vec<String> = Vec::new();
+    for row in rows {
+        if let Value::Object(ref fields) = *row {
+            for (name, value) in fields {
+                if !names.contains(name) && cell(value).is_some() {
+                    names.push(name.clone());
+                }
+            }
+        }
+    }
+
+    let preferred: Vec<String> = columns
+        .iter()
+        .filter(|c| names.iter().any(|n| n == *c))
+        .map(|c| (*c).to_owned())
+        .collect();
+    if !preferred.is_empty() {
+        names = preferred;
+    }
+
+    let table: Vec<Vec<String>> = rows
+        .iter()
+        .map(|row| {
+            names
+                .iter()
+                .map(|name| row.get(name).and_then(cell).unwrap_or_default())
+                .collect()
+        })
+        .collect();
+
+    let widths: Vec<usize> = names
+        .iter()
+        .enumerate()
+        .map(|(i, name)| {
+            table
+                .iter()
+                .map(|r| r[i].chars().count())
+                .chain(Some(name.chars().count()))
+                .max()
+                .unwrap_or(0)
+        })
+        .collect();
+
+    // list item attributes only hold rank, that is worth showing
+    let headers: Vec<String> = names
+        .iter()
+        .map(|n| if n == "@attr" { "rank".to_owned() } else { n.clone() })
+        .collect();
+
+    print_line(&headers, &widths);
+    for row in &table {
+        print_line(row, &widths);
+    }
+}
+
+fn print_line(cells: &[String], widths: &[usize]) {
+    let line: Vec<String> = cells
+        .iter()
+        .zip(widths)
+        .map(|(cell, &width)| format!("{:width$}", cell, width = width))
+        .collect();
+    println!("{}", line.join("  ").trim_end());
+}
+
+fn print_pairs(value: &Value) {
+    let mut pairs = Vec::new();
+    flatten("", value, &mut pairs);
+    let width = pairs.iter().map(|(k, _)| k.chars().count()).max().unwrap_or(0);
+    for (key, value) in pairs {
+        println!("{:width$}  {}", key, value, width = width);
+    }
+}
+
+fn flatten(prefix: &str, value: &Value, pairs: &mut Vec<(String, String)>) {
+    match *value {
+        Value::Object(ref fields) => {
+            for (name, value) in fields {
+                let key = if prefix.is_empty() { name.clone() } else { format!("{}.{}", prefix, name) };
+                flatten(&key, value, pairs);
+            }
+        }
+        Value::Array(ref items) => {
+            for (i, value) in items.iter().enumerate() {
+                flatten(&format!("{}[{}]", prefix, i), value, pairs);
+            }
+        }
+        Value::Null => {}
+        _ => {
+            if let Some(text) = cell(value) {
+                pairs.push((prefix.to_owned(), text));
+            }
+        }
+    }
+}
@@ -0,0 +1,143 @@
+use std::fmt::Debug;
+use std::future::Future;
+use std::sync::Arc;
+
+use url::Url;
+use serde_json::Value;
+
+use lastfm::{LastfmType, RequestParams};
+use lastfm::track::{UpdateNowPlaying, Scrobble};
+
+use crate::client::{Client as AsyncClient, Builder};
+use crate::runtime::{self, Runtime};
+use crate::lookup::Subject;
+use crate::scrobbler::Track;
+use crate::utils::Result;
+use crate::write::Submission;
+
+// ----------------------------------------------------------------
+
+/// Synchronous last.fm API client
+///
+/// Owns a dedicated runtime (check `runtime::dedicated()`) and blocks on it, so callers
+/// don't have to manage one. Runtime set with `Builder::runtime()` is replaced by it.
+/// Every method blocks until the response is received, so they must not be called
+/// from within asynchronous code. The client is `Send` and `Sync`.
+///
+/// ## Example:
+/// ```
+/// use lastfm_parse_rs::user::{Params, GetInfo};
+/// use first_fm::{Client, Result};
+///
+/// let client = first_fm::blocking::Client::new(Client::builder().api_key(LASTFM_API_KEY)).unwrap();
+///
+/// let mut _buf = String::new();
+/// let res: Result<GetInfo> = client.request(&mut _buf, Params::GetInfo { user: "xenzh" });
+///
+/// println!("Result: {:?}", res);
+/// ```
+pub struct Client {
+    client: AsyncClient,
+    runtime: Arc<dyn Runtime>,
+}
+
+impl Client {
+    /// Builds new blocking client from client builder configuration
+    pub fn new(config: Builder) -> Result<Client> {
+        let runtime = runtime::dedicated()?;
+        let client = config.runtime(runtime.clone()).build()?;
+        Ok(Client { client, runtime })
+    }
+
+    /// Returns underlying asynchronous client, that shares configuration and session with this one
+    pub fn client(&self) -> &AsyncClient {
+        &self.client
+    }
+
+    /// Synchronous version of `Client::request()`
+    pub fn request<'rsp, T, P>(&self, storage: &'rsp mut String, params: P) -> Result<T>
+    where
+        P: RequestParams + Debug,
+        T: LastfmType<'rsp>,
+    {
+        self.run(self.client.request(storage, params))
+    }
+
+    /// Synchronous version of `Client::call_raw()`
+    pub fn call_raw<I, K, V>(&self, method: &str, params: I, signed: bool) -> Result<Value>
+    where
+        I: IntoIterator<Item = (K, V)>,
+        K: AsRef<str>,
+        V: AsRef<str>,
+    {
+        self.run(self.client.call_raw(method, params, signed))
+    }
+
+    /// Synchronous version of `Client::mobile_auth()`
+    pub fn mobile_auth(&self, username: &str, password: &str) -> Result<()> {
+        self.run(self.client.mobile_auth(username, password))
+    }
+
+    /// Synchronous version of `Client::init_desktop_auth()`
+    pub fn init_desktop_auth(&self) -> Result<Url> {
+        self.run(self.client.init_desktop_auth())
+    }
+
+    /// Synchronous version of `Client::finalize_desktop_auth()`
+    pub fn finalize_desktop_auth(&self) -> Result<()> {
+        self.run(self.client.finalize_desktop_auth())
+    }
+
+    /// Checks if the client is authenticated and therefore able to call `write` API methods
+    pub fn is_authenticated(&self) -> bool {
+        self.client.is_authenticated()
+    }
+
+    /// Synchronous version of `Client::love_track()`
+    pub fn love_track(&self, artist: &str, track: &str) -> Result<()> {
+        self.run(self.client.love_track(artist, track))
+    }
+
+    /// Synchronous version of `Client::unlove_track()`
+    pub fn unlove_track(&self, artist: &str, track: &str) -> Result<()> {
+        self.run(self.client.unlove_track(artist, track))
+    }
+
+    /// Synchronous version of `Client::add_tags()`
+    pub fn add_tags(&self, subject: &Subject, tags: &[&str]) -> Result<()> {
+        self.run(self.client.add_tags(subject, tags))
+    }
+
+    /// Synchronous version of `Client::remove_tag()`
+    pub fn remove_tag(&self, subject: &Subject, tag: &str) -> Result<()> {
+        self.run(self.client.remove_tag(subject, tag))
+    }
+
+    /// Synchronous version of `Client::update_now_playing()`
+    pub fn update_now_playing<'rsp>(
+        &self,
+        storage: &'rsp mut String,
+        track: &Track,
+    ) -> Result<UpdateNowPlaying<'rsp>> {
+        self.run(self.client.update_now_playing(storage, track))
+    }
+
+    /// Synchronous version of `Client::scrobble()`
+    pub fn scrobble<'rsp>(&self, storage: &'rsp mut String, tracks: &[Track]) -> Result<Scrobble<'rsp>> {
+        self.run(self.client.scrobble(storage, tracks))
+    }
+
+    /// Synchronous version of `Client::update_now_playing_submission()`
+    pub fn update_now_playing_submission(&self, track: &Track) -> Result<Submission> {
+        self.run(self.client.update_now_playing_submission(track))
+    }
+
+    /// Synchronous version of `Client::scrobble_submission()`
+    pub fn scrobble_submission(&self, tracks: &[Track]) -> Result<Vec<Submission>> {
+        self.run(self.client.scrobble_submission(tracks))
+    }
+
+    fn run<F: Future>(&self, future: F) -> F::Output {
+        runtime::block_on(&*self.runtime, future)
+    }
+}
@@ -0,0 +1,317 @@
+use std::collections::{HashMap, VecDeque};
+use std::fs::{self, File};
+use std::io::{Read, Write};
+use std::path::{Path, PathBuf};
+use std::process;
+use std::sync::{Arc, Mutex};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::time::Duration;
+
+use url::Url;
+
+use crate::clock::{Clock, SystemClock};
+use crate::utils::Result;
+
+// ----------------------------------------------------------------
+
+/// Storage for cached response bodies.
+///
+/// Implementations have to take care of expiration by themselves:
+/// `get()` should never return bodies older than their TTL.
+pub trait Store: Send + Sync {
+    /// Returns cached body for given key along with the time left until it expires,
+    /// if there's one and it's not expired
+    fn get(&self, key: &str) -> Option<(String, Duration)>;
+
+    /// Stores response body for given key
+    fn put(&self, key: &str, body: &str, ttl: Duration);
+}
+
+// ----------------------------------------------------------------
+
+struct Entry {
+    body: String,
+    expires: Duration,
+}
+
+struct Lru {
+    entries: HashMap<String, Entry>,
+    order: VecDeque<String>,
+}
+
+impl Lru {
+    fn touch(&mut self, key: &str) {
+        if let Some(pos) = self.order.iter().position(|k| k == key) {
+            let key = self.order.remove(pos).unwrap();
+            self.order.push_back(key);
+        }
+    }
+
+    fn remove(&mut self, key: &str) {
+        self.entries.remove(key);
+        if let Some(pos) = self.order.iter().position(|k| k == key) {
+            self.order.remove(pos);
+        }
+    }
+}
+
+/// In-memory LRU store with fixed capacity
+pub struct MemoryStore {
+    capacity: usize,
+    lru: Mutex<Lru>,
+    clock: Arc<dyn Clock>,
+}
+
+impl MemoryStore {
+    /// Constructs new store, that holds at most `capacity` responses
+    pub fn new(capacity: usize) -> MemoryStore {
+        MemoryStore {
+            capacity,
+            lru: Mutex::new(Lru {
+                entries: HashMap::new(),
+                order: VecDeque::new(),
+            }),
+            clock: Arc::new(SystemClock::new()),
+        }
+    }
+
+    /// Sets clock, that entries expire by (system one is used by default)
+    pub fn clock<C: Clock + 'static>(mut self, clock: C) -> MemoryStore {
+        self.clock = Arc::new(clock);
+        self
+    }
+}
+
+impl Store for MemoryStore {
+    fn get(&self, key: &str) -> Option<(String, Duration)> {
+        let mut lru = self.lru.lock().unwrap();
+
+        let now = self.clock.now();
+        let expired = match lru.entries.get(key) {
+            Some(entry) => entry.expires <= now,
+            None => return None,
+        };
+        if expired {
+            lru.remove(key);
+            return None;
+        }
+
+        lru.touch(key);
+        lru.entries.get(key).map(|e| (e.body.clone(), e.expires - now))
+    }
+
+    fn put(&self, key: &str, body: &str, ttl: Duration) {
+        if self.capacity == 0 {
+            return;
+        }
+        let mut lru = self.lru.lock().unwrap();
+
+        lru.remove(key);
+        while lru.order.len() >= self.capacity {
+            if let Some(oldest) = lru.order.pop_front() {
+                lru.entries.remove(&oldest);
+            }
+        }
+
+        lru.entries.insert(key.to_owned(), Entry {
+            body: body.to_owned(),
+            expires: self.clock.now() + ttl,
+        });
+        lru.order.push_back(key.to_owned());
+    }
+}
+
+// ----------------------------------------------------------------
+
+/// On-disk store, keeps each response in a separate file inside given directory.
+///
+/// File name is an md5 hash of the key, first line of the file holds
+/// expiration time (unix timestamp), the rest is response body.
+/// Files are written to a temporary file first and then renamed,
+/// so readers never see partially written ones.
+pub struct DiskStore {
+    dir: PathBuf,
+    clock: Arc<dyn Clock>,
+}
+
+impl DiskStore {
+    /// Constructs new store, creating the directory if needed
+    pub fn new<P: Into<PathBuf>>(dir: P) -> Result<DiskStore> {
+        let dir = dir.into();
+        fs::create_dir_all(&dir)?;
+        Ok(DiskStore {
+            dir,
+            clock: Arc::new(SystemClock::new()),
+        })
+    }
+
+    /// Sets clock, that expiration timestamps come from (system one is used by default)
+    pub fn clock<C: Clock + 'static>(mut self, clock: C) -> DiskStore {
+        self.clock = Arc::new(clock);
+        self
+    }
+
+    fn path(&self, key: &str) -> PathBuf {
+        self.dir.join(format!("{:x}", md5::compute(key.as_bytes())))
+    }
+
+    /// Unique temporary file name for given target, so concurrent writers don't share it
+    fn temp_path(path: &Path) -> PathBuf {
+        static NEXT: AtomicUsize = AtomicUsize::new(0);
+
+        let mut name = path.as_os_str().to_owned();
+        name.push(format!(".{}.{}.tmp", process::id(), NEXT.fetch_add(1, Ordering::Relaxed)));
+        PathBuf::from(name)
+    }
+}
+
+impl Store for DiskStore {
+    fn get(&self, key: &str) -> Option<(String, Duration)> {
+        let path = self.path(key);
+
+        let mut contents = String::new();
+        File::open(&path)
+            .and_then(|mut f| f.read_to_string(&mut contents))
+            .ok()?;
+
+        let split = contents.find('\n')?;
+        let expires: u64 = contents[..split].parse().ok()?;
+        let now = self.clock.unix_time();
+        if expires <= now {
+            let _ = fs::remove_file(&path);
+            return None;
+        }
+
+        Some((contents[split + 1..].to_owned(), Duration::from_secs(expires - now)))
+    }
+
+    fn put(&self, key: &str, body: &str, ttl: Duration) {
+        let expires = self.clock.unix_time() + ttl.as_secs();
+        let path = self.path(key);
+        let temp = DiskStore::temp_path(&path);
+
+        let written = File::create(&temp).and_then(|mut f| {
+            write!(f, "{}\n{}", expires, body)?;
+            f.sync_all()
+        });
+        if written.and_then(|_| fs::rename(&temp, &path)).is_err() {
+            let _ = fs::remove_file(&temp);
+        }
+    }
+}
+
+// ----------------------------------------------------------------
+
+/// Response cache for read-only API methods
+///
+/// Only methods that have a TTL configured are cached. TTL can be set for a
+/// method family (like `artist`) or for a specific method (like `artist.getInfo`),
+/// the latter takes precedence.
+///
+/// Responses are looked up in the in-memory LRU first, then in the backing
+/// store (if any). Signed requests are never cached.
+///
+/// ## Example:
+/// ```
+/// let cache = Cache::new(256)
+///     .ttl("artist", Duration::from_secs(24 * 60 * 60))
+///     .ttl("track.getInfo", Duration::from_secs(60 * 60))
+///     .store(DiskStore::new("/tmp/first-fm")?);
+///
+/// let client = Client::builder()
+///     .api_key(LASTFM_API_KEY)
+///     .cache(cache)
+///     .build()?;
+/// ```
+pub struct Cache {
+    ttls: HashMap<String, Duration>,
+    memory: MemoryStore,
+    store: Option<Box<dyn Store>>,
+}
+
+impl Cache {
+    /// Constructs new cache with in-memory LRU of given capacity and no TTLs set
+    pub fn new(capacity: usize) -> Cache {
+        Cache {
+            ttls: HashMap::new(),
+            memory: MemoryStore::new(capacity),
+            store: None,
+        }
+    }
+
+    /// Sets TTL for a method family (`artist`) or a single method (`artist.getInfo`)
+    pub fn ttl(mut self, method: &str, ttl: Duration) -> Cache {
+        self.ttls.insert(method.to_owned(), ttl);
+        self
+    }
+
+    /// Sets clock of in-memory entries expiration, backing store keeps its own one
+    pub fn clock<C: Clock + 'static>(mut self, clock: C) -> Cache {
+        self.memory = self.memory.clock(clock);
+        self
+    }
+
+    /// Sets backing store, that is consulted on in-memory cache miss
+    pub fn store<S: Store + 'static>(mut self, store: S) -> Cache {
+        self.store = Some(Box::new(store));
+        self
+    }
+
+    /// Returns TTL configured for given method, if any
+    pub fn ttl_for(&self, method: &str) -> Option<Duration> {
+        self.ttls.get(method).cloned().or_else(|| {
+            method.split('.').next().and_then(
+                |family| self.ttls.get(family).cloned(),
+            )
+        })
+    }
+
+    /// Looks up cached response body
+    pub fn get(&self, key: &str) -> Option<String> {
+        if let Some((body, _)) = self.memory.get(key) {
+            return Some(body);
+        }
+
+        // promoted entry keeps the expiration time it was stored with
+        let store = self.store.as_ref()?;
+        let (body, left) = store.get(key)?;
+        self.memory.put(key, &body, left);
+        Some(body)
+    }
+
+    /// Stores response body
+    pub fn put(&self, key: &str, body: &str, ttl: Duration) {
+        self.memory.put(key, body, ttl);
+        if let Some(ref store) = self.store {
+            store.put(key, body, ttl);
+        }
+    }
+}
+
+// ----------------------------------------------------------------
+
+/// Returns canonical representation of (unsigned) request url, used as a cache key.
+///
+/// Query parameters are sorted, so the key does not depend on their order.
+/// Session key and signature are dropped, although signed requests aren't supposed to be cached.
+pub fn canonical_key(url: &Url) -> String {
+    let mut pairs: Vec<(String, String)> = url.query_pairs()
+        .filter(|(k, _)| k != "sk" && k != "api_sig")
+        .map(|(k, v)| (k.into_owned(), v.into_owned()))
+        .collect();
+    pairs.sort();
+
+    let mut key = url.clone();
+    key.set_query(None);
+    key.query_pairs_mut().extend_pairs(pairs.iter());
+    key.into()
+}
+
+/// Extracts API method name from request url (or canonical key)
+pub fn method_of(url: &str) -> Option<String> {
+    let url: Url = url.parse().ok()?;
+    let method = url.query_pairs()
+        .find(|(k, _)| k == "method")
+        .map(|(_, v)| v.into_owned());
+    method
+}
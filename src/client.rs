@@ -1,42 +1,51 @@
-
 use std::fmt::Debug;
+use std::future::Future;
 use std::io::ErrorKind as IoErrorKind;
-use std::net::{ToSocketAddrs, SocketAddr};
+use std::net::SocketAddr;
+use std::sync::{Arc, RwLock, Mutex};
+use std::time::Instant;
 
 use url::Url;
+use serde_json::Value;
 
-use tokio_core::reactor::{Core, Handle};
-use tokio_core::net::TcpStreamNew;
-
-use native_tls::TlsConnector;
-use tokio_tls::TlsConnectorExt;
 
 use lastfm::{LastfmType, Request, RequestParams, from_json_str};
 use lastfm::auth::{Params as AuthParams, GetMobileSession, GetToken, GetSession};
 
-use async_http_client::prelude::*;
-use async_http_client::HttpRequest;
-
 // ----------------------------------------------------------------
 
 use super::{LASTFM_API_BASE_URL, LASTFM_API_AUTH_URL};
-use utils::{Error, Result, Data};
+use crate::utils::{Error, Result};
+use crate::raw::{RawRequest, api_sig, api_error, check_api_error};
+use crate::cache::{Cache, canonical_key, method_of};
+use crate::trace::{self, Instrument, RequestInfo, ResponseInfo};
+use crate::http::{self, Reply};
+use crate::runtime::{self, Runtime};
+use crate::service::{Legacy, Service};
 
 // ----------------------------------------------------------------
 
 /// Client builder
 ///
-/// Base and desktop auth urls are automatically set to defaults.
+/// Base and desktop auth urls are automatically set to defaults,
+/// other compatible services can be targeted with `service()`.
 ///
-/// To make `read` calls API key and Tokio reactor core handle have to be set.
+/// To make `read` calls API key has to be set.
+/// Requests are run on `runtime::default()` runtime, unless other one is set.
 ///
 /// To make `auth` and `write` calls secret has to be set.
 pub struct Builder {
     base_url: String,
     auth_url: String,
+    handshake_url: Option<String>,
     api_key: Option<String>,
     secret: Option<String>,
-    handle: Option<Handle>,
+    session_key: Option<String>,
+    user: Option<String>,
+    insecure_handshake: bool,
+    cache: Option<Cache>,
+    instrument: Option<Arc<dyn Instrument>>,
+    runtime: Option<Arc<dyn Runtime>>,
 }
 
 impl Builder {
@@ -45,36 +54,64 @@ impl Builder {
         Builder {
             base_url: LASTFM_API_BASE_URL.to_owned(),
             auth_url: LASTFM_API_AUTH_URL.to_owned(),
+            handshake_url: None,
             api_key: None,
             secret: None,
-            handle: None,
+            session_key: None,
+            user: None,
+            insecure_handshake: false,
+            cache: None,
+            instrument: None,
+            runtime: None,
         }
     }
 
     /// Builds new client from builder configuration
     pub fn build(self) -> Result<Client> {
-        let base_url: Url = self.base_url.parse().map_err(|e| Error::build(e))?;
-        let auth_url: Url = self.auth_url.parse().map_err(|e| Error::build(e))?;
+        let base_url: Url = self.base_url.parse().map_err(Error::build)?;
+        let auth_url: Url = self.auth_url.parse().map_err(Error::build)?;
+        let legacy = match self.handshake_url {
+            Some(url) => {
+                let url: Url = url.parse().map_err(Error::build)?;
+                // handshake sends the session key in the query
+                if url.scheme() != "https" && !self.insecure_handshake {
+                    return Err(Error::build(format!("Audioscrobbler handshake url {} is not https", url)));
+                }
+                let user = self
+                    .user
+                    .ok_or_else(|| Error::build("Missing user name, legacy submission protocol needs it"))?;
+                Some(Legacy::new(url, user))
+            }
+            None => None,
+        };
 
-        let api_key = self.api_key.ok_or(Error::build("Missing API key"))?;
-        let handle = self.handle.ok_or(
-            Error::build("Missing Tokio reactor core handle"),
-        )?;
+        let api_key = self.api_key.ok_or_else(|| Error::build("Missing API key"))?;
 
-        let addr = base_url.to_socket_addrs()?.next().ok_or(Error::build(
-            "No socket address found in base url",
-        ))?;
+        let addr = base_url
+            .socket_addrs(|| None)?
+            .into_iter()
+            .next()
+            .ok_or_else(|| Error::build("No socket address found in base url"))?;
+
+        let runtime = match self.runtime {
+            Some(runtime) => runtime,
+            None => runtime::default()?,
+        };
 
-        Ok(Client {
-            base_url: base_url,
-            auth_url: auth_url,
+        let shared = Shared {
+            base_url,
+            auth_url,
             socket_addr: addr,
-            api_key: api_key,
+            api_key,
             secret: self.secret,
-            session: None,
-            token: None,
-            handle: handle,
-        })
+            session: RwLock::new(self.session_key),
+            token: Mutex::new(None),
+            legacy,
+            cache: self.cache,
+            instrument: self.instrument,
+            runtime,
+        };
+        Ok(Client { shared: Arc::new(shared) })
     }
 
     /// Updates base API url
@@ -89,15 +126,45 @@ impl Builder {
         self
     }
 
-    /// Sets API key
-    pub fn api_key(mut self, api_key: &str) -> Builder {
-        self.api_key = Some(api_key.to_owned());
+    /// Targets given scrobbling service: sets its endpoints and submission protocol.
+    ///
+    /// ## Example:
+    /// ```
+    /// let client = Client::builder()
+    ///     .service(Service::LibreFm)
+    ///     .api_key(LIBREFM_API_KEY)
+    ///     .secret(LIBREFM_API_SECRET)
+    ///     .build()?;
+    /// ```
+    pub fn service(mut self, service: Service) -> Builder {
+        if let Some(url) = service.base_url() {
+            self.base_url = url;
+        }
+        if let Some(url) = service.auth_url() {
+            self.auth_url = url;
+        }
+        self.handshake_url = service.handshake_url();
         self
     }
 
-    /// Sets Tokio reactor core handle
-    pub fn handle(mut self, handle: Handle) -> Builder {
-        self.handle = Some(handle);
+    /// Sets user name. It's only needed (and required) for services with legacy submission protocol,
+    /// which makes handshakes on behalf of the user (check `Service`).
+    pub fn user(mut self, user: &str) -> Builder {
+        self.user = Some(user.to_owned());
+        self
+    }
+
+    /// Allows plain http handshake url for legacy submission protocol.
+    ///
+    /// Handshake sends the session key in cleartext then, so it's only meant for local servers and tests.
+    pub fn insecure_handshake(mut self) -> Builder {
+        self.insecure_handshake = true;
+        self
+    }
+
+    /// Sets API key
+    pub fn api_key(mut self, api_key: &str) -> Builder {
+        self.api_key = Some(api_key.to_owned());
         self
     }
 
@@ -106,82 +173,86 @@ impl Builder {
         self.secret = Some(secret.to_owned());
         self
     }
-}
 
-// ----------------------------------------------------------------
+    /// Sets session key, obtained earlier by one of the auth methods (check `Client::session_key()`).
+    ///
+    /// Client built with a session key is authenticated right away.
+    pub fn session_key(mut self, session_key: &str) -> Builder {
+        self.session_key = Some(session_key.to_owned());
+        self
+    }
 
-macro_rules! request {
-    ($client:ident, $url:expr, $method:expr) => {
-        match $url.scheme() {
-            "http" => {
-                Box::new($client.get_stream().map_err(From::from).and_then($method))
-            },
-            "https" => {
-                let tls = TlsConnector::builder().unwrap().build().unwrap();
-                Box::new($client.get_stream().map_err(From::from).and_then(move |stream| {
-                    tls.connect_async($url.domain().unwrap(), stream)
-                        .map_err(From::from)
-                        .and_then($method)
-                }))
-            },
-            _ => Box::new(err(Error::io(IoErrorKind::InvalidInput, "no scheme in url"))),
-        }
+    /// Sets instrumentation hooks, that are notified about every request sent by the client
+    pub fn instrument<I: Instrument + 'static>(mut self, instrument: I) -> Builder {
+        self.instrument = Some(Arc::new(instrument));
+        self
     }
-}
 
-macro_rules! get {
-    ($url:expr, $buf:ident) => {
-        |stream| {
-            result(HttpRequest::get($url).map_err(|e| Error::io(IoErrorKind::Other, e)))
-                .and_then(send!(stream, $buf))
-        }
+    /// Enables response cache for read-only methods (disabled by default)
+    pub fn cache(mut self, cache: Cache) -> Builder {
+        self.cache = Some(cache);
+        self
     }
-}
 
-macro_rules! post {
-    ($base_url:ident, $url:ident, $buf:ident) => {
-        move |stream| {
-            let query = $url.query().unwrap_or("");
-            result(HttpRequest::post($base_url, query).map_err(|e| Error::io(IoErrorKind::Other, e)))
-                .and_then(send!(stream, $buf))
-        }
+    /// Sets async runtime, that is used to connect, spawn tasks and run timers
+    pub fn runtime<R: Runtime + 'static>(mut self, runtime: R) -> Builder {
+        self.runtime = Some(Arc::new(runtime));
+        self
     }
 }
 
-macro_rules! send {
-    ($stream:expr, $buf:ident) => {
-        |req| {
-            req.send($stream).map_err(From::from).and_then(move |res| {
-                if let (Some(resp), _) = res {
-                    // serde doesnt support inplace escape sequence decoding yet
-                    // (see https://github.com/serde-rs/json/issues/318)
-                    *$buf = String::from_utf8_lossy(resp.get_body())
-                        .into_owned()
-                        .replace("\\\"", "'");
-
-                    let data: Result<T> = from_json_str($buf).map_err(From::from);
-                    return result(data);
-                }
-                err(Error::io(IoErrorKind::UnexpectedEof, "no response body"))
-            })
-        }
+impl Default for Builder {
+    fn default() -> Builder {
+        Builder::new()
     }
 }
 
 // ----------------------------------------------------------------
 
-/// last.fm API client
-/// TODO: write something useful about low-level `request` and
-/// high-level `auth` and `scrobble` APIs
-pub struct Client {
+/// Stores response body in given storage and parses a last.fm data object from it
+pub(crate) fn decode<'rsp, T>(storage: &'rsp mut String, body: String) -> Result<T>
+where
+    T: LastfmType<'rsp>,
+{
+    // serde doesnt support inplace escape sequence decoding yet
+    // (see https://github.com/serde-rs/json/issues/318)
+    *storage = body.replace("\\\"", "'");
+    let storage: &'rsp String = storage;
+
+    // error payloads don't fit response types, so they are looked for only when decoding fails
+    from_json_str(storage).map_err(|e| api_error(storage).unwrap_or_else(|| Error::from(e)))
+}
+
+// ----------------------------------------------------------------
+
+/// Client configuration and session, shared between client clones
+struct Shared {
     base_url: Url,
     auth_url: Url,
     socket_addr: SocketAddr,
     api_key: String,
     secret: Option<String>,
-    token: Option<String>,
-    session: Option<String>,
-    handle: Handle,
+    token: Mutex<Option<String>>,
+    session: RwLock<Option<String>>,
+    legacy: Option<Legacy>,
+    cache: Option<Cache>,
+    instrument: Option<Arc<dyn Instrument>>,
+    runtime: Arc<dyn Runtime>,
+}
+
+/// last.fm API client
+/// TODO: write something useful about low-level `request` and
+/// high-level `auth` and `scrobble` APIs
+///
+/// Client is cheap to clone: clones share configuration, cache and session,
+/// so authenticating one of them authenticates all. It's also `Send` and `Sync`,
+/// and its futures are `Send`, so they can be spawned on multi-threaded runtimes.
+///
+/// Client is not tied to a particular async runtime: connections are opened
+/// through `Runtime` trait object set in builder, check `runtime` module for details.
+#[derive(Clone)]
+pub struct Client {
+    shared: Arc<Shared>,
 }
 
 impl Client {
@@ -197,8 +268,6 @@ impl Client {
     /// has to implement `LastfmType` - both these traits and itheir implementations
     /// can be found in [lastfm-parse-rs crate](https://xenzh.github.io/lastfm-parse-rs/).
     ///
-    /// This method returns a future that won't ever resolve unless consumed by event loop.
-    ///
     /// Note that this method somewhat awkwardly requires a mutable string to be supplied.
     /// Reason for this is that all `lastfm_parse_rs` types heavily rely on so-called zero-cost
     /// deserialization recently introduced in serde. The idea is that instead of copying, string
@@ -208,50 +277,105 @@ impl Client {
     ///
     /// ## Example:
     /// ```
-    /// use tokio_core::reactor::Core;
     /// use lastfm_parse_rs::user::{Params, GetInfo};
     /// use first_fm::{Client, Result};
     ///
-    /// let mut core = Core::new().unwrap();
-    /// let handle = core.handle();
-    ///
     /// let client = Client::builder()
     ///     .api_key(LASTFM_API_KEY)
-    ///     .handle(handle.clone())
     ///     .build()
     ///     .unwrap();
     ///
     /// let mut _buf = String::new();
-    /// let me = client.request(&mut _buf, Params::GetInfo { user: "xenzh" });
-    /// let res: Result<GetInfo> = core.run(info);
+    /// let res: Result<GetInfo> = client.request(&mut _buf, Params::GetInfo { user: "xenzh" }).await;
     ///
     /// println!("Result: {:?}", res);
     /// ```
-    pub fn request<'rq, 'rsp, T, P>(
-        &self,
-        storage: &'rsp mut String,
-        params: P,
-    ) -> Data<'rsp, T>
+    ///
+    /// If client has a cache configured, read-only responses may be served from it.
+    /// Use `request_fresh()` to bypass cache lookup.
+    pub async fn request<'rsp, T, P>(&self, storage: &'rsp mut String, params: P) -> Result<T>
+    where
+        P: RequestParams + Debug,
+        T: LastfmType<'rsp>,
+    {
+        let (url, is_post) = self.prepare(params)?;
+        let body = self.fetch(url, is_post, false).await?;
+        decode(storage, body)
+    }
+
+    /// Same as `request()`, but always goes to the network.
+    ///
+    /// Cache (if configured) is updated with the fresh response.
+    pub async fn request_fresh<'rsp, T, P>(&self, storage: &'rsp mut String, params: P) -> Result<T>
+    where
+        P: RequestParams + Debug,
+        T: LastfmType<'rsp>,
+    {
+        let (url, is_post) = self.prepare(params)?;
+        let body = self.fetch(url, is_post, true).await?;
+        decode(storage, body)
+    }
+
+    /// Builds full request url for given parameters, tells whether it should be POSTed
+    pub(crate) fn prepare<P>(&self, params: P) -> Result<(Url, bool)>
     where
         P: RequestParams + Debug,
-        T: LastfmType<'rsp> + Send + 'rsp,
     {
         let is_post = params.needs_signature();
-        let secret = self.secret.as_ref().map(|s| s.as_str());
-        let session = self.session.as_ref().map(|s| s.as_str());
-
-        let rq = Request::new(self.base_url.as_str(), &self.api_key, secret, session, params);
-        match rq.get_url() {
-            Ok(url) => {
-                if is_post {
-                    let base_url = self.base_url.clone();
-                    request!(self, url, post!(base_url, url, storage))
-                } else {
-                    request!(self, url, get!(url, storage))
-                }
-            }
-            Err(e) => Box::new(err(From::from(e))),
-        }
+        let session = self.session();
+
+        let rq = Request::new(
+            self.shared.base_url.as_str(),
+            &self.shared.api_key,
+            self.secret(),
+            session.as_deref(),
+            params,
+        );
+        Ok((rq.get_url()?, is_post))
+    }
+
+    /// Starts building a raw request for arbitrary API method.
+    ///
+    /// This is an escape hatch for methods that are not (yet) modelled by `lastfm_parse_rs`:
+    /// method name and parameters are taken as is, while API key, session key and
+    /// signature are injected by the client. Check `RawRequest` for details.
+    pub fn raw_request(&self, method: &str) -> RawRequest {
+        RawRequest::new(self, method)
+    }
+
+    /// Calls arbitrary API method and resolves into dynamic JSON value.
+    ///
+    /// This is a shortcut for `raw_request()`: the call goes through the same transport
+    /// and error detection as `request()`, so last.fm error payloads are still reported
+    /// as `Error::Lastfm`. Use `raw_request(method).send()` to get undecoded response body.
+    ///
+    /// ## Example:
+    /// ```
+    /// let love = client.call_raw("track.love", vec![("artist", "cher"), ("track", "believe")], true);
+    /// let res: Result<Value> = love.await;
+    /// ```
+    pub async fn call_raw<I, K, V>(&self, method: &str, params: I, signed: bool) -> Result<Value>
+    where
+        I: IntoIterator<Item = (K, V)>,
+        K: AsRef<str>,
+        V: AsRef<str>,
+    {
+        self.raw_request(method).params(params).signed(signed).send_json().await
+    }
+
+    /// Computes API method signature for given parameters using client's shared secret.
+    ///
+    /// Fails if the secret was not set in builder.
+    /// Check https://www.last.fm/api/authspec#_8-signing-calls for details.
+    pub fn api_sig<K, V>(&self, params: &[(K, V)]) -> Result<String>
+    where
+        K: AsRef<str>,
+        V: AsRef<str>,
+    {
+        let secret = self.shared.secret.as_ref().ok_or_else(|| {
+            Error::io(IoErrorKind::NotFound, "Can't sign the request: no shared secret set")
+        })?;
+        Ok(api_sig(params, secret))
     }
 
     /// This is a `high-level` client API method for one of the possible ways to authenticate.
@@ -263,17 +387,12 @@ impl Client {
     /// Once this method succeeds, client will be able to successfully call `write` API methods.
     ///
     /// Check https://www.last.fm/api/mobileauth for details.
-    pub fn mobile_auth(&mut self, core: &mut Core, username: &str, password: &str) -> Result<()> {
+    pub async fn mobile_auth(&self, username: &str, password: &str) -> Result<()> {
         let mut _buf = String::new();
-        let auth = self.request(
-            &mut _buf,
-            AuthParams::GetMobileSession {
-                username: username,
-                password: password,
-            },
-        );
-        let resp: GetMobileSession = core.run(auth)?;
-        self.session = Some(resp.key.to_owned());
+        let resp: GetMobileSession = self
+            .request(&mut _buf, AuthParams::GetMobileSession { username, password })
+            .await?;
+        *self.shared.session.write().unwrap() = Some(resp.key.to_owned());
         Ok(())
     }
 
@@ -284,19 +403,16 @@ impl Client {
     /// After that `finalize_desktop_auth()` should be called to complete auth process.
     ///
     /// Check https://www.last.fm/api/desktopauth and `finalize_desktop_auth()` for details.
-    pub fn init_desktop_auth(&mut self, core: &mut Core) -> Result<Url> {
+    pub async fn init_desktop_auth(&self) -> Result<Url> {
         let mut _buf = String::new();
-        let get_token = self.request(&mut _buf, AuthParams::GetToken);
-        let resp: GetToken = core.run(get_token)?;
+        let resp: GetToken = self.request(&mut _buf, AuthParams::GetToken).await?;
 
-        self.token = Some(resp.token.to_owned());
+        *self.shared.token.lock().unwrap() = Some(resp.token.to_owned());
 
-        let mut url = self.auth_url.clone();
-        {
-            let mut query = url.query_pairs_mut();
-            query.append_pair("api_key", &self.api_key);
-            query.append_pair("token", resp.token);
-        }
+        let mut url = self.shared.auth_url.clone();
+        url.query_pairs_mut()
+            .append_pair("api_key", &self.shared.api_key)
+            .append_pair("token", resp.token);
 
         Ok(url)
     }
@@ -309,26 +425,178 @@ impl Client {
     /// Once this method succeeds, client will be able to successfully call `write` API methods.
     ///
     /// Check https://www.last.fm/api/desktopauth and `init_desktop_auth()` for details.
-    pub fn finalize_desktop_auth(&mut self, core: &mut Core) -> Result<()> {
+    pub async fn finalize_desktop_auth(&self) -> Result<()> {
         let mut _buf = String::new();
-        let token = self.token.take().ok_or(Error::io(
+        let token = self.shared.token.lock().unwrap().take().ok_or_else(|| Error::io(
             IoErrorKind::NotFound,
-            "Desktop session was not initiated (no auth token found)"
+            "Desktop session was not initiated (no auth token found)",
         ))?;
 
-        let get_session = self.request(&mut _buf, AuthParams::GetSession { token: &token });
-        let resp: GetSession = core.run(get_session)?;
-        self.session = Some(resp.key.to_owned());
+        let resp: GetSession = self.request(&mut _buf, AuthParams::GetSession { token: &token }).await?;
+        *self.shared.session.write().unwrap() = Some(resp.key.to_owned());
 
         Ok(())
     }
 
     /// Checks if the client is authenticated and therefore able to call `write` API methods
     pub fn is_authenticated(&self) -> bool {
-        self.session.is_some()
+        self.shared.session.read().unwrap().is_some()
+    }
+
+    /// Returns session key of an authenticated client.
+    ///
+    /// Session keys don't expire, so it can be stored and passed to `Builder::session_key()` later.
+    pub fn session_key(&self) -> Option<String> {
+        self.session()
     }
 
-    fn get_stream(&self) -> TcpStreamNew {
-        TcpStream::connect(&self.socket_addr, &self.handle)
+    /// Replaces session key, like the one renewed by another process (check `session::Session`).
+    ///
+    /// Clones of the client and scrobblers built on it use the new key right away.
+    pub fn set_session_key(&self, session_key: &str) {
+        *self.shared.session.write().unwrap() = Some(session_key.to_owned());
+    }
+
+    /// Returns async runtime the client runs on
+    pub fn runtime(&self) -> Arc<dyn Runtime> {
+        self.shared.runtime.clone()
+    }
+
+    pub(crate) fn base_url(&self) -> &Url {
+        &self.shared.base_url
+    }
+
+    pub(crate) fn legacy(&self) -> Option<&Legacy> {
+        self.shared.legacy.as_ref()
+    }
+
+    pub(crate) fn api_key(&self) -> &str {
+        &self.shared.api_key
+    }
+
+    pub(crate) fn secret(&self) -> Option<&str> {
+        self.shared.secret.as_deref()
+    }
+
+    pub(crate) fn session(&self) -> Option<String> {
+        self.shared.session.read().unwrap().clone()
+    }
+
+    /// Sends prepared request url and resolves into raw response body.
+    /// Signed requests are sent as POST, the rest are sent as GET.
+    ///
+    /// Unsigned requests for methods with configured TTL go through the cache,
+    /// `refresh` skips the lookup but still stores the response.
+    pub(crate) async fn fetch(&self, url: Url, is_post: bool, refresh: bool) -> Result<String> {
+        let cache = match self.shared.cache.as_ref().filter(|_| !is_post) {
+            Some(cache) => cache,
+            None => return self.send(url, is_post).await,
+        };
+        let ttl = match method_of(url.as_str()).and_then(|method| cache.ttl_for(&method)) {
+            Some(ttl) => ttl,
+            None => return self.send(url, is_post).await,
+        };
+
+        let key = canonical_key(&url);
+        if !refresh {
+            if let Some(body) = cache.get(&key) {
+                return Ok(body);
+            }
+        }
+
+        let body = self.send(url, is_post).await?;
+        // error payloads are not worth caching
+        let valid = serde_json::from_str::<Value>(&body)
+            .ok()
+            .map_or(false, |v| check_api_error(&v).is_ok());
+        if valid {
+            cache.put(&key, &body, ttl);
+        }
+        Ok(body)
+    }
+
+    async fn send(&self, url: Url, is_post: bool) -> Result<String> {
+        let info = RequestInfo::new(&url, is_post);
+        let reply = self.observe(&info, false, self.transmit(&url, is_post)).await?;
+        reply.check().map_err(|e| {
+            warn!("<- {} rejected: {}", info.url, e);
+            e
+        })
+    }
+
+    /// Runs the exchange, logging it and notifying instrumentation hooks.
+    ///
+    /// Only redacted url is ever logged, secret bodies (`auth.*` responses or the ones
+    /// marked with `secret_body`) are replaced by `***`.
+    async fn observe<F>(&self, info: &RequestInfo, secret_body: bool, exchange: F) -> Result<Reply>
+    where
+        F: Future<Output = Result<Reply>>,
+    {
+        let instrument = self.shared.instrument.as_ref();
+        let started = Instant::now();
+
+        debug!("-> {} {}", if info.is_post { "POST" } else { "GET" }, info.url);
+        if let Some(instrument) = instrument {
+            instrument.on_request(info);
+        }
+
+        let res = exchange.await;
+        let elapsed = started.elapsed();
+
+        match res {
+            Ok(reply) => {
+                debug!("<- {} {} ({:?}, {} bytes)", reply.status, info.url, elapsed, reply.body.len());
+                let body = if secret_body { "***" } else { trace::redact_body(info.method.as_deref(), &reply.body) };
+                trace!("<- {}", body);
+                if let Some(instrument) = instrument {
+                    let response = ResponseInfo {
+                        status: reply.status,
+                        elapsed,
+                        body,
+                    };
+                    instrument.on_response(info, &response);
+                }
+                Ok(reply)
+            }
+            Err(e) => {
+                warn!("<- {} failed after {:?}: {}", info.url, elapsed, e);
+                if let Some(instrument) = instrument {
+                    instrument.on_error(info, &e, elapsed);
+                }
+                Err(e)
+            }
+        }
+    }
+
+    async fn transmit(&self, url: &Url, is_post: bool) -> Result<Reply> {
+        let request = if is_post {
+            http::post(&self.shared.base_url, url.query().unwrap_or(""))?
+        } else {
+            http::get(url)?
+        };
+
+        http::exchange(&*self.shared.runtime, self.shared.socket_addr, url, &request).await
+    }
+
+    /// Sends request to arbitrary url and resolves into plain text response body.
+    /// Form is POSTed if it's given, GET is sent otherwise.
+    ///
+    /// Used by legacy submission protocol, which sends tracks to urls obtained in the handshake.
+    pub(crate) async fn send_text(&self, url: &Url, form: Option<&str>) -> Result<String> {
+        let addr = http::resolve(url).await?;
+        let request = match form {
+            Some(form) => http::post(url, form)?,
+            None => http::get(url)?,
+        };
+
+        let info = RequestInfo::new(url, form.is_some());
+        // handshake (the only GET) replies with submission session id
+        let exchange = http::exchange(&*self.shared.runtime, addr, url, &request);
+        let reply = self.observe(&info, form.is_none(), exchange).await?;
+
+        if reply.status < 200 || reply.status >= 300 {
+            return Err(Error::http(reply.status, reply.headers, &reply.body));
+        }
+        Ok(reply.body)
     }
 }
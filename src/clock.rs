@@ -0,0 +1,146 @@
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::{Arc, Mutex};
+use std::task::{Context, Poll, Waker};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+use crate::runtime::{Runtime, Sleep};
+
+// ----------------------------------------------------------------
+
+/// Source of time for the scrobbler and response cache.
+///
+/// Scrobbler measures played time, waits for scrobble points and stamps plays
+/// with this clock, so tests can run minutes of playback instantly with `MockClock`.
+/// Cache stores expire their entries by it.
+pub trait Clock: Send + Sync {
+    /// Monotonic time, elapsed since arbitrary (but fixed) origin
+    fn now(&self) -> Duration;
+
+    /// Wall clock time, seconds since unix epoch
+    fn unix_time(&self) -> u64;
+
+    /// Returns timer future, that resolves once `now()` reaches given deadline.
+    ///
+    /// Default implementation sleeps on given runtime.
+    fn sleep_until(&self, runtime: &dyn Runtime, deadline: Duration) -> Sleep {
+        runtime.sleep(deadline.checked_sub(self.now()).unwrap_or_default())
+    }
+}
+
+// ----------------------------------------------------------------
+
+/// Clock, that follows system time. Scrobbler uses it by default.
+#[derive(Debug, Clone)]
+pub struct SystemClock {
+    origin: Instant,
+}
+
+impl SystemClock {
+    pub fn new() -> SystemClock {
+        SystemClock { origin: Instant::now() }
+    }
+}
+
+impl Default for SystemClock {
+    fn default() -> SystemClock {
+        SystemClock::new()
+    }
+}
+
+impl Clock for SystemClock {
+    fn now(&self) -> Duration {
+        self.origin.elapsed()
+    }
+
+    fn unix_time(&self) -> u64 {
+        SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_secs())
+            .unwrap_or(0)
+    }
+}
+
+// ----------------------------------------------------------------
+
+#[derive(Debug)]
+struct MockState {
+    now: Duration,
+    unix_origin: u64,
+    sleepers: Vec<Waker>,
+}
+
+/// Clock, that only moves when it's advanced manually.
+///
+/// Clones share the time, so one can be given to the scrobbler and another one
+/// advanced by the test. Timers wake up as soon as the clock passes their deadlines.
+///
+/// ## Example:
+/// ```
+/// let clock = MockClock::new(1513719309);
+/// let scrobbler = Scrobbler::builder(&client).clock(clock.clone()).build()?;
+/// scrobbler.now_playing(Some(Track::new("touching ii", "iamthemorning", 244)));
+/// clock.advance(Duration::from_secs(130));
+/// ```
+#[derive(Debug, Clone)]
+pub struct MockClock {
+    state: Arc<Mutex<MockState>>,
+}
+
+impl MockClock {
+    /// Constructs clock, that starts at given unix time
+    pub fn new(unix_time: u64) -> MockClock {
+        let state = MockState {
+            now: Duration::from_secs(0),
+            unix_origin: unix_time,
+            sleepers: Vec::new(),
+        };
+        MockClock { state: Arc::new(Mutex::new(state)) }
+    }
+
+    /// Moves the clock forward, waking up timers that are due
+    pub fn advance(&self, duration: Duration) {
+        let sleepers = {
+            let mut state = self.state.lock().unwrap();
+            state.now += duration;
+            std::mem::take(&mut state.sleepers)
+        };
+        // sleepers that aren't due yet register themselves again when polled
+        for waker in sleepers {
+            waker.wake();
+        }
+    }
+}
+
+impl Clock for MockClock {
+    fn now(&self) -> Duration {
+        self.state.lock().unwrap().now
+    }
+
+    fn unix_time(&self) -> u64 {
+        let state = self.state.lock().unwrap();
+        state.unix_origin + state.now.as_secs()
+    }
+
+    fn sleep_until(&self, _runtime: &dyn Runtime, deadline: Duration) -> Sleep {
+        Box::pin(MockSleep { state: self.state.clone(), deadline })
+    }
+}
+
+struct MockSleep {
+    state: Arc<Mutex<MockState>>,
+    deadline: Duration,
+}
+
+impl Future for MockSleep {
+    type Output = ();
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<()> {
+        let mut state = self.state.lock().unwrap();
+        if state.now >= self.deadline {
+            return Poll::Ready(());
+        }
+        state.sleepers.push(cx.waker().clone());
+        Poll::Pending
+    }
+}
@@ -0,0 +1,239 @@
+use std::fmt::Debug;
+use std::future::Future as StdFuture;
+
+use url::Url;
+use serde_json::Value;
+
+use futures::{FutureExt, TryFutureExt};
+use futures01::Future;
+use tokio_core::reactor::{Core, Handle};
+
+use lastfm::{LastfmType, RequestParams};
+
+use crate::client::{Client as AsyncClient, Builder as AsyncBuilder, decode};
+use crate::runtime;
+use crate::scrobbler::{Scrobbler as AsyncScrobbler, Track};
+use crate::utils::{Error, Result};
+
+// ----------------------------------------------------------------
+
+/// Boxed futures 0.1 future, as returned by pre-0.2 client methods
+pub type Data<'de, T> = Box<dyn Future<Item = T, Error = Error> + Send + 'de>;
+
+// ----------------------------------------------------------------
+
+/// Client builder of tokio-core based versions
+///
+/// Has the same setters as it used to, settings added in 0.2 (runtime, cache, service and such)
+/// are available through `with()`.
+pub struct Builder {
+    builder: AsyncBuilder,
+    handle: Option<Handle>,
+}
+
+impl Builder {
+    /// Constructs new client builder
+    pub fn new() -> Builder {
+        Builder {
+            builder: AsyncBuilder::new(),
+            handle: None,
+        }
+    }
+
+    /// Builds new client from builder configuration
+    pub fn build(self) -> Result<Client> {
+        Client::new(self.builder)
+    }
+
+    /// Updates base API url
+    pub fn base_url(self, url: &str) -> Builder {
+        self.with(|b| b.base_url(url))
+    }
+
+    /// Updates base desktop auth url
+    pub fn auth_url(self, url: &str) -> Builder {
+        self.with(|b| b.auth_url(url))
+    }
+
+    /// Sets API key
+    pub fn api_key(self, api_key: &str) -> Builder {
+        self.with(|b| b.api_key(api_key))
+    }
+
+    /// Sets Tokio reactor core handle.
+    ///
+    /// Requests run on client's runtime, returned futures can be run on the core
+    /// of this handle (or any other one), so it's optional now.
+    pub fn handle(mut self, handle: Handle) -> Builder {
+        self.handle = Some(handle);
+        self
+    }
+
+    /// Sets API shared secret
+    pub fn secret(self, secret: &str) -> Builder {
+        self.with(|b| b.secret(secret))
+    }
+
+    /// Applies settings of std futures builder, that have no tokio-core counterparts
+    pub fn with<F: FnOnce(AsyncBuilder) -> AsyncBuilder>(mut self, configure: F) -> Builder {
+        self.builder = configure(self.builder);
+        self
+    }
+}
+
+impl Default for Builder {
+    fn default() -> Builder {
+        Builder::new()
+    }
+}
+
+// ----------------------------------------------------------------
+
+/// Client with futures 0.1 API of tokio-core based versions
+///
+/// Requests are spawned on client's runtime (check `Builder::runtime()`),
+/// returned futures only wait for them, so they can be run on any tokio-core reactor.
+/// Auth methods take the reactor core and block on it, as they used to.
+///
+/// ## Example:
+/// ```
+/// use tokio_core::reactor::Core;
+/// use lastfm_parse_rs::user::{Params, GetInfo};
+/// use first_fm::compat::Client;
+///
+/// let mut core = Core::new().unwrap();
+/// let client = Client::builder()
+///     .api_key(LASTFM_API_KEY)
+///     .handle(core.handle())
+///     .build()
+///     .unwrap();
+///
+/// let mut _buf = String::new();
+/// let res: Result<GetInfo> = core.run(client.request(&mut _buf, Params::GetInfo { user: "xenzh" }));
+/// ```
+pub struct Client {
+    client: AsyncClient,
+}
+
+impl Client {
+    /// Returns new client builder
+    pub fn builder() -> Builder {
+        Builder::new()
+    }
+
+    /// Builds new compat client from std futures client builder configuration
+    pub fn new(config: AsyncBuilder) -> Result<Client> {
+        Ok(Client { client: config.build()? })
+    }
+
+    /// Returns underlying asynchronous client, that shares configuration and session with this one
+    pub fn client(&self) -> &AsyncClient {
+        &self.client
+    }
+
+    /// Futures 0.1 version of `Client::request()`
+    pub fn request<'rsp, T, P>(&self, storage: &'rsp mut String, params: P) -> Data<'rsp, T>
+    where
+        P: RequestParams + Debug,
+        T: LastfmType<'rsp> + Send + 'rsp,
+    {
+        let prepared = self.client.prepare(params);
+        let client = self.client.clone();
+
+        let body = self.spawn(async move {
+            let (url, is_post) = prepared?;
+            client.fetch(url, is_post, false).await
+        });
+        Box::new(body.and_then(move |body| decode(storage, body)))
+    }
+
+    /// Futures 0.1 version of `Client::call_raw()`
+    pub fn call_raw<I, K, V>(&self, method: &str, params: I, signed: bool) -> Data<'static, Value>
+    where
+        I: IntoIterator<Item = (K, V)>,
+        K: AsRef<str>,
+        V: AsRef<str>,
+    {
+        let client = self.client.clone();
+        let method = method.to_owned();
+        let params: Vec<(String, String)> = params
+            .into_iter()
+            .map(|(k, v)| (k.as_ref().to_owned(), v.as_ref().to_owned()))
+            .collect();
+
+        self.spawn(async move { client.call_raw(&method, params, signed).await })
+    }
+
+    /// Futures 0.1 version of `Client::mobile_auth()`, runs to completion on given core
+    pub fn mobile_auth(&self, core: &mut Core, username: &str, password: &str) -> Result<()> {
+        let client = self.client.clone();
+        let (username, password) = (username.to_owned(), password.to_owned());
+        core.run(self.spawn(async move { client.mobile_auth(&username, &password).await }))
+    }
+
+    /// Futures 0.1 version of `Client::init_desktop_auth()`, runs to completion on given core
+    pub fn init_desktop_auth(&self, core: &mut Core) -> Result<Url> {
+        let client = self.client.clone();
+        core.run(self.spawn(async move { client.init_desktop_auth().await }))
+    }
+
+    /// Futures 0.1 version of `Client::finalize_desktop_auth()`, runs to completion on given core
+    pub fn finalize_desktop_auth(&self, core: &mut Core) -> Result<()> {
+        let client = self.client.clone();
+        core.run(self.spawn(async move { client.finalize_desktop_auth().await }))
+    }
+
+    /// Checks if the client is authenticated and therefore able to call `write` API methods
+    pub fn is_authenticated(&self) -> bool {
+        self.client.is_authenticated()
+    }
+
+    /// Runs std future on client's runtime and returns futures 0.1 future of its result.
+    ///
+    /// This is a way to call the rest of `Client` API (lookups, `write` methods and such)
+    /// from futures 0.1 code.
+    pub fn spawn<F, T>(&self, future: F) -> Data<'static, T>
+    where
+        F: StdFuture<Output = Result<T>> + Send + 'static,
+        T: Send + 'static,
+    {
+        let joined = runtime::spawn_with_result(&*self.client.runtime(), future).map(|res| res?);
+        Box::new(Box::pin(joined).compat())
+    }
+}
+
+// ----------------------------------------------------------------
+
+/// Scrobbler with futures 0.1 API
+///
+/// Scrobbler runs on its own threads, so only `flush()` returns a future.
+pub struct Scrobbler {
+    scrobbler: AsyncScrobbler,
+}
+
+impl Scrobbler {
+    /// Constructs scrobbler, that submits tracks with given client
+    pub fn new(client: &Client) -> Result<Scrobbler> {
+        Ok(Scrobbler { scrobbler: AsyncScrobbler::new(client.client())? })
+    }
+
+    /// Returns underlying scrobbler
+    pub fn scrobbler(&self) -> &AsyncScrobbler {
+        &self.scrobbler
+    }
+
+    /// Reports currently playing track, `None` means playback is paused or stopped
+    pub fn now_playing(&self, track: Option<Track>) {
+        self.scrobbler.now_playing(track)
+    }
+
+    /// Caches finished track and submits it, see `scrobbler::Scrobbler::scrobble()`
+    pub fn scrobble(&self, track: Track) {
+        self.scrobbler.scrobble(track)
+    }
+
+    /// Futures 0.1 version of `scrobbler::Scrobbler::flush()`
+    pub fn flush(&self) -> Data<'static, Vec<Track>> {
+        Box::new(Box::pin(self.scrobbler.flush()).compat())
+    }
+}
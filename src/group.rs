@@ -0,0 +1,121 @@
+use std::panic;
+use std::path::{Path, PathBuf};
+use std::thread::{self, spawn, JoinHandle};
+use std::time::Duration;
+
+use crate::player::PlayerSource;
+use crate::scrobbler::{Driver, Scrobbler, ShutdownReport};
+use crate::utils::{Error, Result};
+
+// ----------------------------------------------------------------
+
+/// Scrobblers of several sessions, that follow the same player.
+///
+/// Every member keeps its own cache, corrections and failure state, so a session
+/// that is offline or revoked doesn't hold back scrobbles of the others.
+///
+/// ## Example:
+/// ```
+/// let group = Group::new()
+///     .add("alice", Scrobbler::with_cache_file(&alice, "alice.scrobbles")?)?
+///     .add("bob", Scrobbler::with_cache_file(&bob, "bob.scrobbles")?)?;
+/// group.follow(MpdSource::new(Mpd::connect(MPD_DEFAULT_ADDRESS)?));
+///
+/// if group.needs_auth().contains(&"bob") {
+///     // session was renewed elsewhere, submit what's kept
+///     bob.set_session_key(&Session::load(BOB_SESSION)?.unwrap().key);
+///     group.get("bob").unwrap().flush().await?;
+/// }
+/// ```
+#[derive(Default)]
+pub struct Group {
+    members: Vec<(String, Scrobbler)>,
+}
+
+impl Group {
+    /// Constructs empty group
+    pub fn new() -> Group {
+        Group::default()
+    }
+
+    /// Adds named scrobbler to the group.
+    ///
+    /// Fails if the scrobbler saves its cache to a file another member uses,
+    /// as they would overwrite each other's scrobbles.
+    pub fn add<N: Into<String>>(mut self, name: N, scrobbler: Scrobbler) -> Result<Group> {
+        let name = name.into();
+        let taken: Vec<PathBuf> = self.members.iter().flat_map(|(_, s)| cache_files(s)).collect();
+        if let Some(path) = cache_files(&scrobbler).into_iter().find(|p| taken.contains(p)) {
+            let message = format!("Cache file {} of {} is used by another scrobbler", path.display(), name);
+            return Err(Error::build(message));
+        }
+
+        self.members.push((name, scrobbler));
+        Ok(self)
+    }
+
+    /// Returns scrobbler with given name
+    pub fn get(&self, name: &str) -> Option<&Scrobbler> {
+        self.members.iter().find(|(n, _)| n == name).map(|(_, s)| s)
+    }
+
+    /// Iterates over named scrobblers, in order they were added
+    pub fn iter(&self) -> impl Iterator<Item = (&str, &Scrobbler)> {
+        self.members.iter().map(|(n, s)| (n.as_str(), s))
+    }
+
+    /// Returns number of scrobblers in the group
+    pub fn len(&self) -> usize {
+        self.members.len()
+    }
+
+    /// Returns `true` if there are no scrobblers in the group
+    pub fn is_empty(&self) -> bool {
+        self.members.is_empty()
+    }
+
+    /// Returns driver, that passes player events to every scrobbler in the group
+    pub fn driver(&self) -> Driver {
+        Driver::merge(self.members.iter().map(|(_, s)| s.driver()))
+    }
+
+    /// Follows given player source on a separate thread, see `Scrobbler::follow()`
+    pub fn follow<S>(&self, mut source: S) -> JoinHandle<()>
+    where
+        S: PlayerSource + Send + 'static,
+    {
+        let driver = self.driver();
+        spawn(move || driver.run(&mut source))
+    }
+
+    /// Returns names of scrobblers, whose sessions have to be authenticated again.
+    ///
+    /// This only reports the state: scrobbles of those members are kept, but not submitted
+    /// until the caller renews their sessions (see `Client::set_session_key()`) and calls `Scrobbler::flush()`.
+    pub fn needs_auth(&self) -> Vec<&str> {
+        self.iter().filter(|(_, s)| s.needs_auth()).map(|(n, _)| n).collect()
+    }
+
+    /// Shuts down all scrobblers at once, see `Scrobbler::shutdown()`
+    pub fn shutdown(self, deadline: Duration) -> Vec<(String, ShutdownReport)> {
+        thread::scope(|scope| {
+            let stopping: Vec<_> = self
+                .members
+                .into_iter()
+                .map(|(name, scrobbler)| (name, scope.spawn(move || scrobbler.shutdown(deadline))))
+                .collect();
+            stopping
+                .into_iter()
+                .map(|(name, handle)| match handle.join() {
+                    Ok(report) => (name, report),
+                    Err(payload) => panic::resume_unwind(payload),
+                })
+                .collect()
+        })
+    }
+}
+
+/// Returns cache files of all scrobbler's queues
+fn cache_files(scrobbler: &Scrobbler) -> Vec<PathBuf> {
+    scrobbler.queues().iter().filter_map(|q| q.cache_file()).map(Path::to_owned).collect()
+}
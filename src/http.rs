@@ -0,0 +1,254 @@
+use std::io::ErrorKind as IoErrorKind;
+use std::net::SocketAddr;
+use std::thread;
+use std::time::Duration;
+
+use url::Url;
+use serde_json::Value;
+
+use futures::channel::oneshot;
+use futures::future::{select, Either};
+use futures::io::{AsyncRead, AsyncWrite, AsyncReadExt, AsyncWriteExt};
+
+use crate::runtime::Runtime;
+use crate::utils::{Error, Result};
+
+// ----------------------------------------------------------------
+
+/// Time given to connect, send the request and read the whole response (in seconds)
+pub static REQUEST_TIMEOUT_SEC: u64 = 30;
+
+/// Max size of a response (head and body) in bytes, larger ones are dropped
+pub static MAX_RESPONSE_LEN: u64 = 8 * 1024 * 1024;
+
+// ----------------------------------------------------------------
+
+/// Raw HTTP response, as received by the transport
+pub(crate) struct Reply {
+    pub status: u16,
+    pub headers: Vec<(String, String)>,
+    pub body: String,
+}
+
+impl Reply {
+    /// Returns value of the first header with given name (case-insensitive)
+    pub fn header(&self, name: &str) -> Option<&str> {
+        self.headers
+            .iter()
+            .find(|(n, _)| n.eq_ignore_ascii_case(name))
+            .map(|(_, v)| v.as_str())
+    }
+
+    /// Checks HTTP status and body format before the body is handed to decoders.
+    ///
+    /// last.fm reports API errors with non-2xx statuses too, so such replies are let through
+    /// if the body carries an error code, to be reported as `Error::Lastfm`.
+    pub fn check(self) -> Result<String> {
+        let trimmed = self.body.trim_start();
+        let is_json = trimmed.starts_with('{') || trimmed.starts_with('[');
+        let is_success = self.status >= 200 && self.status < 300;
+
+        if !is_success && !has_error_code(&self.body) {
+            return Err(Error::http(self.status, self.headers, &self.body));
+        }
+        if !is_json {
+            let content_type = self.header("content-type").map(|s| s.to_owned());
+            return Err(Error::not_json(content_type, &self.body));
+        }
+        Ok(self.body)
+    }
+}
+
+/// Tells whether the body is a last.fm error payload (`{"error": N, ...}`)
+fn has_error_code(body: &str) -> bool {
+    serde_json::from_str::<Value>(body)
+        .ok()
+        .and_then(|value| value.get("error").and_then(Value::as_u64))
+        .is_some()
+}
+
+// ----------------------------------------------------------------
+
+fn host(url: &Url) -> Result<String> {
+    let host = url.host_str().ok_or_else(|| Error::io(IoErrorKind::InvalidInput, "no host in url"))?;
+    Ok(match url.port() {
+        Some(port) => format!("{}:{}", host, port),
+        None => host.to_owned(),
+    })
+}
+
+/// Serializes GET request for given url
+pub(crate) fn get(url: &Url) -> Result<Vec<u8>> {
+    let path = match url.query() {
+        Some(query) => format!("{}?{}", url.path(), query),
+        None => url.path().to_owned(),
+    };
+    Ok(format!(
+        "GET {} HTTP/1.1\r\nHost: {}\r\nAccept: application/json\r\nConnection: close\r\n\r\n",
+        path,
+        host(url)?
+    ).into_bytes())
+}
+
+/// Serializes form-encoded POST request for given url
+pub(crate) fn post(url: &Url, form: &str) -> Result<Vec<u8>> {
+    Ok(format!(
+        "POST {} HTTP/1.1\r\nHost: {}\r\nAccept: application/json\r\nConnection: close\r\n\
+         Content-Type: application/x-www-form-urlencoded\r\nContent-Length: {}\r\n\r\n{}",
+        url.path(),
+        host(url)?,
+        form.len(),
+        form
+    ).into_bytes())
+}
+
+/// Serializes JSON POST request for given url, with optional `Authorization` header value
+pub(crate) fn post_json(url: &Url, authorization: Option<&str>, body: &str) -> Result<Vec<u8>> {
+    let authorization = match authorization {
+        Some(value) => format!("Authorization: {}\r\n", value),
+        None => String::new(),
+    };
+    Ok(format!(
+        "POST {} HTTP/1.1\r\nHost: {}\r\nAccept: application/json\r\nConnection: close\r\n{}\
+         Content-Type: application/json\r\nContent-Length: {}\r\n\r\n{}",
+        url.path(),
+        host(url)?,
+        authorization,
+        body.len(),
+        body
+    ).into_bytes())
+}
+
+/// Resolves socket address of the url host.
+///
+/// DNS lookup blocks, so it's made on a separate thread to keep runtime workers free.
+pub(crate) async fn resolve(url: &Url) -> Result<SocketAddr> {
+    let (tx, rx) = oneshot::channel();
+    let url = url.clone();
+    thread::spawn(move || {
+        let addr = url.socket_addrs(|| None).map(|addrs| addrs.into_iter().next());
+        let _ = tx.send(addr);
+    });
+
+    rx.await
+        .map_err(|_| Error::io(IoErrorKind::Interrupted, "Address lookup was cancelled"))??
+        .ok_or_else(|| Error::io(IoErrorKind::AddrNotAvailable, "No socket address found in url"))
+}
+
+/// Connects to given address and sends serialized request, https urls are sent over TLS.
+///
+/// Gives up once `REQUEST_TIMEOUT_SEC` passes, so a stalled server can't hold the caller forever.
+pub(crate) async fn exchange(runtime: &dyn Runtime, addr: SocketAddr, url: &Url, request: &[u8]) -> Result<Reply> {
+    let exchange = Box::pin(connect_and_send(runtime, addr, url, request));
+    let timeout = runtime.sleep(Duration::from_secs(REQUEST_TIMEOUT_SEC));
+    match select(exchange, timeout).await {
+        Either::Left((reply, _)) => reply,
+        Either::Right(_) => Err(Error::io(
+            IoErrorKind::TimedOut,
+            format!("No response from {} in {}s", url.host_str().unwrap_or(""), REQUEST_TIMEOUT_SEC),
+        )),
+    }
+}
+
+async fn connect_and_send(runtime: &dyn Runtime, addr: SocketAddr, url: &Url, request: &[u8]) -> Result<Reply> {
+    let stream = runtime.connect(addr).await?;
+    match url.scheme() {
+        "http" => send(stream, request).await,
+        "https" => {
+            let domain = url.domain().ok_or_else(|| {
+                Error::io(IoErrorKind::InvalidInput, "no domain in https url")
+            })?;
+            let stream = async_native_tls::connect(domain, stream).await?;
+            send(stream, request).await
+        }
+        _ => Err(Error::io(IoErrorKind::InvalidInput, "no scheme in url")),
+    }
+}
+
+/// Writes serialized request to the stream and reads the response.
+///
+/// Requests are sent with `Connection: close`, so the response is read until EOF,
+/// but not past `MAX_RESPONSE_LEN`.
+pub(crate) async fn send<S>(mut stream: S, request: &[u8]) -> Result<Reply>
+where
+    S: AsyncRead + AsyncWrite + Unpin,
+{
+    stream.write_all(request).await?;
+    stream.flush().await?;
+
+    let mut raw = Vec::new();
+    stream.take(MAX_RESPONSE_LEN + 1).read_to_end(&mut raw).await?;
+    if raw.len() as u64 > MAX_RESPONSE_LEN {
+        return Err(invalid(format!("response is larger than {} bytes", MAX_RESPONSE_LEN)));
+    }
+    parse(&raw)
+}
+
+fn invalid<E>(inner: E) -> Error
+where
+    E: Into<Box<dyn std::error::Error + Send + Sync>>,
+{
+    Error::io(IoErrorKind::InvalidData, inner)
+}
+
+fn parse(raw: &[u8]) -> Result<Reply> {
+    if raw.is_empty() {
+        return Err(Error::io(IoErrorKind::UnexpectedEof, "no response"));
+    }
+
+    let split = raw
+        .windows(4)
+        .position(|w| w == b"\r\n\r\n")
+        .ok_or_else(|| invalid("malformed HTTP response head"))?;
+    let head = String::from_utf8_lossy(&raw[..split]);
+    let body = &raw[split + 4..];
+
+    let mut lines = head.split("\r\n");
+    let status = lines
+        .next()
+        .and_then(|line| line.split_whitespace().nth(1))
+        .and_then(|code| code.parse().ok())
+        .ok_or_else(|| invalid("malformed HTTP status line"))?;
+
+    let headers: Vec<(String, String)> = lines
+        .filter_map(|line| {
+            let colon = line.find(':')?;
+            Some((line[..colon].trim().to_owned(), line[colon + 1..].trim().to_owned()))
+        })
+        .collect();
+
+    let chunked = headers.iter().any(|(n, v)| {
+        n.eq_ignore_ascii_case("transfer-encoding") && v.eq_ignore_ascii_case("chunked")
+    });
+    let body = if chunked { dechunk(body)? } else { body.to_vec() };
+
+    Ok(Reply {
+        status,
+        headers,
+        body: String::from_utf8_lossy(&body).into_owned(),
+    })
+}
+
+fn dechunk(mut raw: &[u8]) -> Result<Vec<u8>> {
+    let mut body = Vec::new();
+    loop {
+        let eol = raw
+            .windows(2)
+            .position(|w| w == b"\r\n")
+            .ok_or_else(|| invalid("malformed chunk size"))?;
+        let size = String::from_utf8_lossy(&raw[..eol]);
+        let size = usize::from_str_radix(size.split(';').next().unwrap_or("").trim(), 16)
+            .map_err(invalid)?;
+        if size == 0 {
+            return Ok(body);
+        }
+
+        let start = eol + 2;
+        let end = start + size;
+        if raw.len() < end {
+            return Err(Error::io(IoErrorKind::UnexpectedEof, "truncated chunk"));
+        }
+        body.extend_from_slice(&raw[start..end]);
+        raw = &raw[(end + 2).min(raw.len())..];
+    }
+}
@@ -1,14 +1,7 @@
-#![feature(try_from)]
-
-extern crate url;
-extern crate futures;
-extern crate tokio_core;
-extern crate tokio_io;
-extern crate native_tls;
-extern crate tokio_tls;
+#[macro_use]
+extern crate log;
 
 extern crate lastfm_parse_rs as lastfm;
-extern crate async_http_client;
 
 // ----------------------------------------------------------------
 
@@ -18,9 +11,63 @@ pub mod utils;
 /// Contains API client and builder structures
 pub mod client;
 
+/// Contains raw request builder and request signing helpers
+pub mod raw;
+
+/// Contains synchronous client facade
+pub mod blocking;
+
+/// Contains high-level artist, album and track lookups
+pub mod lookup;
+
+/// Contains high-level `write` API (love, tags, now playing)
+pub mod write;
+
+/// Contains response cache for read-only API methods
+pub mod cache;
+
+/// Contains request/response instrumentation hooks
+pub mod trace;
+
+/// Contains async runtime abstraction and its implementations
+pub mod runtime;
+
+/// Contains session key storage
+pub mod session;
+
+/// Contains profiles of last.fm-compatible scrobbling services
+pub mod service;
+
+/// Contains minimal MPD client to follow local player
+pub mod mpd;
+
+/// Contains clocks, that drive the scrobbler and cache expiration
+pub mod clock;
+
+/// Contains scrobble rules, that decide when tracks are scrobbled
+pub mod policy;
+
+/// Contains rule-based track tag normalizer
+pub mod normalize;
+
 /// Contains read-to-use scrobbler, based on a client;
 pub mod scrobbler;
 
+/// Contains scrobble sinks, services that scrobbler submits tracks to
+pub mod sink;
+
+/// Contains player event sources, that drive the scrobbler
+pub mod player;
+
+/// Contains multi-session scrobbling
+pub mod group;
+
+/// Contains futures 0.1 API of tokio-core based versions, for code written against them
+#[cfg(feature = "compat")]
+pub mod compat;
+
+mod http;
+
 #[cfg(test)]
 mod tests;
 
@@ -38,3 +85,7 @@ pub static LASTFM_API_AUTH_URL: &str = "https://www.last.fm/api/auth/";
 
 pub use utils::{Error, Result, Data};
 pub use client::{Client, Builder};
+pub use service::Service;
+pub use raw::RawRequest;
+pub use lookup::{ArtistQuery, SimilarArtistsQuery, ArtistTopTracksQuery, AlbumQuery, TrackQuery, SimilarTracksQuery};
+pub use lookup::{ArtistSearchQuery, AlbumSearchQuery, TrackSearchQuery};
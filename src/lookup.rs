@@ -0,0 +1,332 @@
+use lastfm::LastfmType;
+use lastfm::artist::{GetInfo as ArtistInfo, GetSimilar as SimilarArtists,
+                     GetTopTracks as ArtistTopTracks, Search as ArtistSearch};
+use lastfm::album::{GetInfo as AlbumInfo, Search as AlbumSearch};
+use lastfm::track::{GetInfo as TrackInfo, GetSimilar as SimilarTracks, Search as TrackSearch};
+
+use crate::client::{Client, decode};
+use crate::utils::Result;
+
+// ----------------------------------------------------------------
+
+/// Subject of a tag or lookup: either a name (pair) or a MusicBrainz id
+#[derive(Debug, Clone)]
+pub enum Subject {
+    /// Artist name
+    Artist(String),
+    /// Artist and album names
+    Album(String, String),
+    /// Artist and track names
+    Track(String, String),
+    /// MusicBrainz id of an artist, album or track
+    Mbid(String),
+}
+
+/// Parameters shared by all lookups, subject goes first
+#[derive(Debug, Clone, Default)]
+struct Params {
+    subject: Vec<(String, String)>,
+    autocorrect: bool,
+    lang: Option<String>,
+    username: Option<String>,
+    limit: Option<u32>,
+    page: Option<u32>,
+}
+
+impl Params {
+    fn new(subject: &[(&str, &str)]) -> Params {
+        Params {
+            subject: subject.iter().map(|&(k, v)| (k.to_owned(), v.to_owned())).collect(),
+            ..Params::default()
+        }
+    }
+
+    fn into_vec(self) -> Vec<(String, String)> {
+        let mut params = self.subject;
+        if self.autocorrect {
+            params.push(("autocorrect".to_owned(), "1".to_owned()));
+        }
+        if let Some(lang) = self.lang {
+            params.push(("lang".to_owned(), lang));
+        }
+        if let Some(username) = self.username {
+            params.push(("username".to_owned(), username));
+        }
+        if let Some(limit) = self.limit {
+            params.push(("limit".to_owned(), limit.to_string()));
+        }
+        if let Some(page) = self.page {
+            params.push(("page".to_owned(), page.to_string()));
+        }
+        params
+    }
+}
+
+/// Implements option setters for query types
+macro_rules! options {
+    ($query:ident: $($option:ident),*) => {
+        impl $query {
+            $( options!(@ $option $query); )*
+        }
+    };
+    (@ autocorrect $query:ident) => {
+        /// Lets last.fm correct misspelled names (ignored for MBID lookups)
+        pub fn autocorrect(mut self, autocorrect: bool) -> $query {
+            self.0.autocorrect = autocorrect;
+            self
+        }
+    };
+    (@ lang $query:ident) => {
+        /// Sets language for biographies and wiki texts (ISO 639 alpha-2 code)
+        pub fn lang(mut self, lang: &str) -> $query {
+            self.0.lang = Some(lang.to_owned());
+            self
+        }
+    };
+    (@ username $query:ident) => {
+        /// Includes given user's playcount and loved status in the response
+        pub fn username(mut self, username: &str) -> $query {
+            self.0.username = Some(username.to_owned());
+            self
+        }
+    };
+    (@ limit $query:ident) => {
+        /// Sets max number of results for list responses
+        pub fn limit(mut self, limit: u32) -> $query {
+            self.0.limit = Some(limit);
+            self
+        }
+    };
+    (@ page $query:ident) => {
+        /// Sets page number for paginated responses
+        pub fn page(mut self, page: u32) -> $query {
+            self.0.page = Some(page);
+            self
+        }
+    };
+}
+
+// ----------------------------------------------------------------
+
+/// Parameters of `artist.getInfo`
+///
+/// ## Example:
+/// ```
+/// let mut _buf = String::new();
+/// let info = client.artist_info(&mut _buf, ArtistQuery::name("cher").autocorrect(true).lang("de")).await;
+/// println!("Result: {:?}", info);
+/// ```
+#[derive(Debug, Clone)]
+pub struct ArtistQuery(Params);
+
+impl ArtistQuery {
+    /// Looks up an artist by name
+    pub fn name(artist: &str) -> ArtistQuery {
+        ArtistQuery(Params::new(&[("artist", artist)]))
+    }
+
+    /// Looks up an artist by MusicBrainz id
+    pub fn mbid(mbid: &str) -> ArtistQuery {
+        ArtistQuery(Params::new(&[("mbid", mbid)]))
+    }
+}
+
+options!(ArtistQuery: autocorrect, lang, username);
+
+/// Parameters of `artist.getSimilar`
+#[derive(Debug, Clone)]
+pub struct SimilarArtistsQuery(Params);
+
+impl SimilarArtistsQuery {
+    /// Looks up artists similar to given one
+    pub fn name(artist: &str) -> SimilarArtistsQuery {
+        SimilarArtistsQuery(Params::new(&[("artist", artist)]))
+    }
+
+    /// Looks up artists similar to the one with given MusicBrainz id
+    pub fn mbid(mbid: &str) -> SimilarArtistsQuery {
+        SimilarArtistsQuery(Params::new(&[("mbid", mbid)]))
+    }
+}
+
+options!(SimilarArtistsQuery: autocorrect, limit);
+
+/// Parameters of `artist.getTopTracks`
+#[derive(Debug, Clone)]
+pub struct ArtistTopTracksQuery(Params);
+
+impl ArtistTopTracksQuery {
+    /// Looks up top tracks of an artist by name
+    pub fn name(artist: &str) -> ArtistTopTracksQuery {
+        ArtistTopTracksQuery(Params::new(&[("artist", artist)]))
+    }
+
+    /// Looks up top tracks of an artist by MusicBrainz id
+    pub fn mbid(mbid: &str) -> ArtistTopTracksQuery {
+        ArtistTopTracksQuery(Params::new(&[("mbid", mbid)]))
+    }
+}
+
+options!(ArtistTopTracksQuery: autocorrect, limit, page);
+
+/// Parameters of `album.getInfo`, album is looked up along with its artist
+#[derive(Debug, Clone)]
+pub struct AlbumQuery(Params);
+
+impl AlbumQuery {
+    /// Looks up an album by artist and album names
+    pub fn name(artist: &str, album: &str) -> AlbumQuery {
+        AlbumQuery(Params::new(&[("artist", artist), ("album", album)]))
+    }
+
+    /// Looks up an album by MusicBrainz id
+    pub fn mbid(mbid: &str) -> AlbumQuery {
+        AlbumQuery(Params::new(&[("mbid", mbid)]))
+    }
+}
+
+options!(AlbumQuery: autocorrect, lang, username);
+
+/// Parameters of `track.getInfo`, track is looked up along with its artist
+#[derive(Debug, Clone)]
+pub struct TrackQuery(Params);
+
+impl TrackQuery {
+    /// Looks up a track by artist and track names
+    pub fn name(artist: &str, track: &str) -> TrackQuery {
+        TrackQuery(Params::new(&[("artist", artist), ("track", track)]))
+    }
+
+    /// Looks up a track by MusicBrainz id
+    pub fn mbid(mbid: &str) -> TrackQuery {
+        TrackQuery(Params::new(&[("mbid", mbid)]))
+    }
+}
+
+options!(TrackQuery: autocorrect, username);
+
+/// Parameters of `track.getSimilar`
+#[derive(Debug, Clone)]
+pub struct SimilarTracksQuery(Params);
+
+impl SimilarTracksQuery {
+    /// Looks up tracks similar to given one of given artist
+    pub fn name(artist: &str, track: &str) -> SimilarTracksQuery {
+        SimilarTracksQuery(Params::new(&[("artist", artist), ("track", track)]))
+    }
+
+    /// Looks up tracks similar to the one with given MusicBrainz id
+    pub fn mbid(mbid: &str) -> SimilarTracksQuery {
+        SimilarTracksQuery(Params::new(&[("mbid", mbid)]))
+    }
+}
+
+options!(SimilarTracksQuery: autocorrect, limit);
+
+// ----------------------------------------------------------------
+
+/// Parameters of `artist.search`
+#[derive(Debug, Clone)]
+pub struct ArtistSearchQuery(Params);
+
+impl ArtistSearchQuery {
+    /// Searches for artists by name
+    pub fn new(artist: &str) -> ArtistSearchQuery {
+        ArtistSearchQuery(Params::new(&[("artist", artist)]))
+    }
+}
+
+options!(ArtistSearchQuery: limit, page);
+
+/// Parameters of `album.search`
+#[derive(Debug, Clone)]
+pub struct AlbumSearchQuery(Params);
+
+impl AlbumSearchQuery {
+    /// Searches for albums by name
+    pub fn new(album: &str) -> AlbumSearchQuery {
+        AlbumSearchQuery(Params::new(&[("album", album)]))
+    }
+}
+
+options!(AlbumSearchQuery: limit, page);
+
+/// Parameters of `track.search`
+#[derive(Debug, Clone)]
+pub struct TrackSearchQuery(Params);
+
+impl TrackSearchQuery {
+    /// Searches for tracks by name
+    pub fn new(track: &str) -> TrackSearchQuery {
+        TrackSearchQuery(Params::new(&[("track", track)]))
+    }
+
+    /// Narrows down the results to given artist
+    pub fn artist(mut self, artist: &str) -> TrackSearchQuery {
+        self.0.subject.push(("artist".to_owned(), artist.to_owned()));
+        self
+    }
+}
+
+options!(TrackSearchQuery: limit, page);
+
+// ----------------------------------------------------------------
+
+/// High-level `read` API for artist, album and track lookups.
+///
+/// These methods are shortcuts for `request()`: the same transport, cache and
+/// error detection are used, and the same storage considerations apply.
+impl Client {
+    /// Calls `artist.getInfo`
+    pub async fn artist_info<'rsp>(&self, storage: &'rsp mut String, query: ArtistQuery) -> Result<ArtistInfo<'rsp>> {
+        self.lookup(storage, "artist.getInfo", query.0).await
+    }
+
+    /// Calls `artist.getSimilar`
+    pub async fn artist_similar<'rsp>(&self, storage: &'rsp mut String, query: SimilarArtistsQuery) -> Result<SimilarArtists<'rsp>> {
+        self.lookup(storage, "artist.getSimilar", query.0).await
+    }
+
+    /// Calls `artist.getTopTracks`
+    pub async fn artist_top_tracks<'rsp>(&self, storage: &'rsp mut String, query: ArtistTopTracksQuery) -> Result<ArtistTopTracks<'rsp>> {
+        self.lookup(storage, "artist.getTopTracks", query.0).await
+    }
+
+    /// Calls `artist.search`
+    pub async fn artist_search<'rsp>(&self, storage: &'rsp mut String, query: ArtistSearchQuery) -> Result<ArtistSearch<'rsp>> {
+        self.lookup(storage, "artist.search", query.0).await
+    }
+
+    /// Calls `album.getInfo`
+    pub async fn album_info<'rsp>(&self, storage: &'rsp mut String, query: AlbumQuery) -> Result<AlbumInfo<'rsp>> {
+        self.lookup(storage, "album.getInfo", query.0).await
+    }
+
+    /// Calls `album.search`
+    pub async fn album_search<'rsp>(&self, storage: &'rsp mut String, query: AlbumSearchQuery) -> Result<AlbumSearch<'rsp>> {
+        self.lookup(storage, "album.search", query.0).await
+    }
+
+    /// Calls `track.getInfo`
+    pub async fn track_info<'rsp>(&self, storage: &'rsp mut String, query: TrackQuery) -> Result<TrackInfo<'rsp>> {
+        self.lookup(storage, "track.getInfo", query.0).await
+    }
+
+    /// Calls `track.getSimilar`
+    pub async fn track_similar<'rsp>(&self, storage: &'rsp mut String, query: SimilarTracksQuery) -> Result<SimilarTracks<'rsp>> {
+        self.lookup(storage, "track.getSimilar", query.0).await
+    }
+
+    /// Calls `track.search`
+    pub async fn track_search<'rsp>(&self, storage: &'rsp mut String, query: TrackSearchQuery) -> Result<TrackSearch<'rsp>> {
+        self.lookup(storage, "track.search", query.0).await
+    }
+
+    async fn lookup<'rsp, T>(&self, storage: &'rsp mut String, method: &str, params: Params) -> Result<T>
+    where
+        T: LastfmType<'rsp>,
+    {
+        let body = self.raw_request(method).params(params.into_vec()).send().await?;
+        decode(storage, body)
+    }
+}
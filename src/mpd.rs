@@ -0,0 +1,338 @@
+use std::collections::VecDeque;
+use std::io::{BufRead, BufReader, ErrorKind as IoErrorKind, Write};
+use std::net::{TcpStream, ToSocketAddrs};
+use std::time::{Duration, Instant};
+
+use crate::player::{PlayerEvent, PlayerSource};
+use crate::scrobbler::Track;
+use crate::utils::{Error, Result};
+
+// ----------------------------------------------------------------
+
+/// Default MPD server address
+pub static MPD_DEFAULT_ADDRESS: &str = "localhost:6600";
+
+/// Playback state reported by MPD
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum State {
+    /// Playing
+    Play,
+    /// Paused
+    Pause,
+    /// Stopped
+    Stop,
+}
+
+/// Subset of `status` command response
+#[derive(Debug, Clone)]
+pub struct Status {
+    /// Playback state
+    pub state: State,
+    /// Playlist id of current song
+    pub song_id: Option<u32>,
+    /// Position in current song (seconds)
+    pub elapsed: Option<f64>,
+    /// Duration of current song (seconds)
+    pub duration: Option<f64>,
+}
+
+/// Subset of `currentsong` command response
+#[derive(Debug, Clone, Default)]
+pub struct Song {
+    /// Song file uri
+    pub file: String,
+    /// `Title` tag
+    pub title: Option<String>,
+    /// `Artist` tag
+    pub artist: Option<String>,
+    /// `Album` tag
+    pub album: Option<String>,
+    /// `AlbumArtist` tag
+    pub album_artist: Option<String>,
+    /// `Track` tag (number part)
+    pub track: Option<u32>,
+    /// Song duration (seconds)
+    pub duration: Option<f64>,
+    /// `MUSICBRAINZ_TRACKID` tag
+    pub musicbrainz_track_id: Option<String>,
+}
+
+impl Song {
+    /// Converts song to a scrobbler track, fails if it has no title or artist tags
+    pub fn to_track(&self) -> Option<Track> {
+        let (title, artist) = (self.title.as_ref()?, self.artist.as_ref()?);
+        let duration = self.duration.map_or(0, |d| d.round() as u32);
+
+        let mut track = Track::new(title, artist, duration);
+        if let Some(ref album) = self.album {
+            track = track.album(album);
+        }
+        if let Some(ref album_artist) = self.album_artist {
+            track = track.album_artist(album_artist);
+        }
+        if let Some(number) = self.track {
+            track = track.track_number(number);
+        }
+        if let Some(ref mbid) = self.musicbrainz_track_id {
+            track = track.mbid(mbid);
+        }
+        Some(track)
+    }
+}
+
+// ----------------------------------------------------------------
+
+/// Minimal synchronous MPD client
+///
+/// Speaks just enough of the [protocol](https://mpd.readthedocs.io/en/latest/protocol.html)
+/// to follow the player: `status`, `currentsong` and `idle`.
+pub struct Mpd {
+    reader: BufReader<TcpStream>,
+    writer: TcpStream,
+    version: String,
+}
+
+impl Mpd {
+    /// Connects to MPD server
+    pub fn connect<A: ToSocketAddrs>(addr: A) -> Result<Mpd> {
+        let writer = TcpStream::connect(addr)?;
+        let mut reader = BufReader::new(writer.try_clone()?);
+
+        let mut greeting = String::new();
+        reader.read_line(&mut greeting)?;
+        let version = greeting
+            .trim_end()
+            .strip_prefix("OK MPD ")
+            .ok_or_else(|| Error::io(IoErrorKind::InvalidData, "Not an MPD server"))?
+            .to_owned();
+
+        Ok(Mpd { reader, writer, version })
+    }
+
+    /// Returns protocol version reported by the server
+    pub fn version(&self) -> &str {
+        &self.version
+    }
+
+    /// Authenticates with given password
+    pub fn password(&mut self, password: &str) -> Result<()> {
+        self.command(&format!("password {}", quote(password)))?;
+        Ok(())
+    }
+
+    /// Queries player status
+    pub fn status(&mut self) -> Result<Status> {
+        let mut status = Status { state: State::Stop, song_id: None, elapsed: None, duration: None };
+        for (key, value) in self.command("status")? {
+            match key.as_str() {
+                "state" => {
+                    status.state = match value.as_str() {
+                        "play" => State::Play,
+                        "pause" => State::Pause,
+                        _ => State::Stop,
+                    }
+                }
+                "songid" => status.song_id = value.parse().ok(),
+                "elapsed" => status.elapsed = value.parse().ok(),
+                "duration" => status.duration = value.parse().ok(),
+                _ => {}
+            }
+        }
+        Ok(status)
+    }
+
+    /// Queries current song, if there's one
+    pub fn current_song(&mut self) -> Result<Option<Song>> {
+        let pairs = self.command("currentsong")?;
+        if pairs.is_empty() {
+            return Ok(None);
+        }
+
+        let mut song = Song::default();
+        for (key, value) in pairs {
+            match key.as_str() {
+                "file" => song.file = value,
+                "Title" => song.title = Some(value),
+                "Artist" => song.artist = Some(value),
+                "Album" => song.album = Some(value),
+                "AlbumArtist" => song.album_artist = Some(value),
+                // "3/12" or "3"
+                "Track" => song.track = value.split('/').next().and_then(|n| n.trim().parse().ok()),
+                "duration" => song.duration = value.parse().ok(),
+                "MUSICBRAINZ_TRACKID" => song.musicbrainz_track_id = Some(value),
+                "Time" if song.duration.is_none() => song.duration = value.parse().ok(),
+                _ => {}
+            }
+        }
+        Ok(Some(song))
+    }
+
+    /// Returns currently playing track, or `None` if player is paused, stopped
+    /// or current song lacks title or artist.
+    ///
+    /// Result can be passed to `Scrobbler::now_playing()` as is.
+    pub fn now_playing(&mut self) -> Result<Option<Track>> {
+        if self.status()?.state != State::Play {
+            return Ok(None);
+        }
+        Ok(self.current_song()?.and_then(|song| song.to_track()))
+    }
+
+    /// Waits until one of given subsystems (like `player`) changes, returns changed ones
+    pub fn idle(&mut self, subsystems: &[&str]) -> Result<Vec<String>> {
+        let mut command = "idle".to_owned();
+        for subsystem in subsystems {
+            command.push(' ');
+            command.push_str(subsystem);
+        }
+
+        let changed = self
+            .command(&command)?
+            .into_iter()
+            .filter(|(key, _)| key == "changed")
+            .map(|(_, value)| value)
+            .collect();
+        Ok(changed)
+    }
+
+    fn command(&mut self, command: &str) -> Result<Vec<(String, String)>> {
+        self.writer.write_all(command.as_bytes())?;
+        self.writer.write_all(b"\n")?;
+
+        let mut pairs = Vec::new();
+        loop {
+            let mut line = String::new();
+            if self.reader.read_line(&mut line)? == 0 {
+                return Err(Error::io(IoErrorKind::UnexpectedEof, "MPD closed the connection"));
+            }
+
+            let line = line.trim_end_matches('\n');
+            if line == "OK" {
+                return Ok(pairs);
+            }
+            if line.starts_with("ACK ") {
+                return Err(Error::io(IoErrorKind::Other, format!("MPD error: {}", line)));
+            }
+            if let Some(colon) = line.find(": ") {
+                pairs.push((line[..colon].to_owned(), line[colon + 2..].to_owned()));
+            }
+        }
+    }
+}
+
+// ----------------------------------------------------------------
+
+/// Position difference (seconds) between expected and reported one, that is considered a seek
+static SEEK_TOLERANCE_SEC: f64 = 2.0;
+
+struct Snapshot {
+    state: State,
+    song_id: Option<u32>,
+    elapsed: f64,
+    at: Instant,
+}
+
+/// Player source, that follows MPD `player` subsystem
+///
+/// Every player change is compared to the previous state: new song id means track change,
+/// unexpected position jump means seek. Note that repeats of a single song look like seeks
+/// to its start, so they are not scrobbled again.
+///
+/// Source is closed on first connection error.
+pub struct MpdSource {
+    mpd: Mpd,
+    last: Option<Snapshot>,
+    pending: VecDeque<PlayerEvent>,
+    idle: bool,
+}
+
+impl MpdSource {
+    /// Constructs source from connected client, current player state is reported first
+    pub fn new(mpd: Mpd) -> MpdSource {
+        MpdSource {
+            mpd,
+            last: None,
+            pending: VecDeque::new(),
+            idle: false,
+        }
+    }
+
+    fn poll(&mut self) -> Result<()> {
+        let status = self.mpd.status()?;
+        let elapsed = status.elapsed.unwrap_or(0.0);
+        let now = Instant::now();
+        let last = self.last.take();
+
+        let was_stopped = last.as_ref().map_or(true, |l| l.state == State::Stop);
+        let new_song = was_stopped || last.as_ref().map_or(true, |l| l.song_id != status.song_id);
+
+        match status.state {
+            State::Stop => {
+                if !was_stopped {
+                    self.pending.push_back(PlayerEvent::Stop);
+                }
+            }
+            state if new_song => match self.mpd.current_song()?.and_then(|s| s.to_track()) {
+                Some(track) => {
+                    self.pending.push_back(PlayerEvent::TrackChanged(track));
+                    if state == State::Pause {
+                        self.pending.push_back(PlayerEvent::Pause);
+                    }
+                }
+                // untagged songs are not scrobbled
+                None if !was_stopped => self.pending.push_back(PlayerEvent::Stop),
+                None => {}
+            },
+            state => {
+                if let Some(ref last) = last {
+                    let expected = match last.state {
+                        State::Play => last.elapsed + now.duration_since(last.at).as_secs_f64(),
+                        _ => last.elapsed,
+                    };
+                    if (elapsed - expected).abs() > SEEK_TOLERANCE_SEC {
+                        self.pending.push_back(PlayerEvent::Seek(Duration::from_secs_f64(elapsed.max(0.0))));
+                    }
+                    if last.state != state {
+                        self.pending.push_back(match state {
+                            State::Play => PlayerEvent::Play,
+                            _ => PlayerEvent::Pause,
+                        });
+                    }
+                }
+            }
+        }
+
+        self.last = Some(Snapshot {
+            state: status.state,
+            song_id: status.song_id,
+            elapsed,
+            at: now,
+        });
+        Ok(())
+    }
+}
+
+impl PlayerSource for MpdSource {
+    fn next_event(&mut self) -> Option<PlayerEvent> {
+        loop {
+            if let Some(event) = self.pending.pop_front() {
+                return Some(event);
+            }
+
+            let res = if self.idle {
+                self.mpd.idle(&["player"]).and_then(|_| self.poll())
+            } else {
+                self.idle = true;
+                self.poll()
+            };
+            if let Err(e) = res {
+                warn!("MPD source is closed: {}", e);
+                return None;
+            }
+        }
+    }
+}
+
+fn quote(arg: &str) -> String {
+    format!("\"{}\"", arg.replace('\\', "\\\\").replace('"', "\\\""))
+}
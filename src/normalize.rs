@@ -0,0 +1,289 @@
+use std::collections::HashMap;
+use std::fmt;
+use std::fs;
+use std::io::ErrorKind as IoErrorKind;
+use std::path::Path;
+
+use regex::Regex;
+
+use crate::scrobbler::Track;
+use crate::utils::{Error, Result};
+
+// ----------------------------------------------------------------
+
+/// Track field, that a rule applies to
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Field {
+    Name,
+    Artist,
+    Album,
+    AlbumArtist,
+}
+
+impl Field {
+    fn parse(name: &str) -> Option<Field> {
+        match name {
+            "name" | "track" | "title" => Some(Field::Name),
+            "artist" => Some(Field::Artist),
+            "album" => Some(Field::Album),
+            "album_artist" => Some(Field::AlbumArtist),
+            _ => None,
+        }
+    }
+
+    fn get_mut<'t>(&self, track: &'t mut Track) -> Option<&'t mut String> {
+        match *self {
+            Field::Name => Some(&mut track.name),
+            Field::Artist => Some(&mut track.artist),
+            Field::Album => track.album.as_mut(),
+            Field::AlbumArtist => track.album_artist.as_mut(),
+        }
+    }
+}
+
+/// Single normalization rule
+#[derive(Debug, Clone)]
+pub enum Rule {
+    /// Replaces all pattern matches in the field, `$1`-style group references are expanded
+    Replace(Field, Regex, String),
+    /// Moves "feat. X" part of the track name to the artist
+    ExtractFeat,
+    /// Converts all-caps field value to title case
+    TitleCase(Field),
+}
+
+impl Rule {
+    /// Constructs regex rewrite rule
+    pub fn replace(field: Field, pattern: &str, replacement: &str) -> Result<Rule> {
+        let regex = Regex::new(pattern).map_err(|e| invalid(format!("Bad pattern {}: {}", pattern, e)))?;
+        Ok(Rule::Replace(field, regex, replacement.to_owned()))
+    }
+
+    fn apply(&self, track: &mut Track) {
+        match *self {
+            Rule::Replace(field, ref regex, ref replacement) => {
+                if let Some(value) = field.get_mut(track) {
+                    *value = regex.replace_all(value, replacement.as_str()).trim().to_owned();
+                }
+            }
+            Rule::ExtractFeat => extract_feat(track),
+            Rule::TitleCase(field) => {
+                if let Some(value) = field.get_mut(track) {
+                    if is_all_caps(value) {
+                        *value = title_case(value);
+                    }
+                }
+            }
+        }
+    }
+}
+
+fn invalid(message: String) -> Error {
+    Error::io(IoErrorKind::InvalidData, message)
+}
+
+fn extract_feat(track: &mut Track) {
+    thread_local! {
+        static FEAT: Regex = Regex::new(
+            r"(?i)\s*(?:[\(\[]\s*(?:feat\.?|ft\.?|featuring)\s+([^\)\]]+)[\)\]]|\s(?:feat\.?|ft\.?|featuring)\s+(.+)$)"
+        ).unwrap();
+    }
+
+    let found = FEAT.with(|feat| {
+        let captures = feat.captures(&track.name)?;
+        let guests = captures.get(1).or_else(|| captures.get(2))?.as_str().trim().to_owned();
+        let whole = captures.get(0)?.range();
+        Some((whole, guests))
+    });
+
+    if let Some((range, guests)) = found {
+        track.name.replace_range(range, "");
+        track.name = track.name.trim().to_owned();
+        if !track.artist.to_lowercase().contains(&guests.to_lowercase()) {
+            track.artist = format!("{} feat. {}", track.artist, guests);
+        }
+    }
+}
+
+fn is_all_caps(value: &str) -> bool {
+    value.chars().any(|c| c.is_alphabetic()) && !value.chars().any(|c| c.is_lowercase())
+}
+
+fn title_case(value: &str) -> String {
+    let mut result = String::with_capacity(value.len());
+    let mut word_start = true;
+    for c in value.chars() {
+        if word_start {
+            result.extend(c.to_uppercase());
+        } else {
+            result.extend(c.to_lowercase());
+        }
+        word_start = c.is_whitespace() || c == '-' || c == '(' || c == '/';
+    }
+    result
+}
+
+// ----------------------------------------------------------------
+
+/// Track tags before and after normalization
+#[derive(Debug, Clone)]
+pub struct Normalized {
+    pub original: Track,
+    pub normalized: Track,
+}
+
+impl Normalized {
+    /// Returns `true` if any of the tags were changed
+    pub fn changed(&self) -> bool {
+        let (a, b) = (&self.original, &self.normalized);
+        a.name != b.name || a.artist != b.artist || a.album != b.album || a.album_artist != b.album_artist
+    }
+}
+
+fn fmt_track(f: &mut fmt::Formatter, track: &Track) -> fmt::Result {
+    write!(f, "{} - {}", track.artist, track.name)?;
+    if let Some(ref album) = track.album {
+        write!(f, " [{}]", album)?;
+    }
+    Ok(())
+}
+
+impl fmt::Display for Normalized {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        fmt_track(f, &self.original)?;
+        write!(f, " => ")?;
+        fmt_track(f, &self.normalized)
+    }
+}
+
+// ----------------------------------------------------------------
+
+/// Rule-based track tag normalizer
+///
+/// Rules are applied in order, artist aliases are looked up (case-insensitively) afterwards.
+/// Rules can be loaded from a file, one per line, lines starting with `#` are comments:
+///
+/// ```text
+/// # sed-like regex rewrite, any delimiter can be used
+/// replace name /\s*\(Remastered( \d{4})?\)$//
+/// replace artist |\s+-\s+Topic$||
+/// # "Song (feat. X)" by "Artist" becomes "Song" by "Artist feat. X"
+/// feat
+/// # "ARTIST NAME" becomes "Artist Name"
+/// titlecase artist
+/// # artist alias
+/// alias Beatles = The Beatles
+/// ```
+#[derive(Debug, Clone, Default)]
+pub struct Normalizer {
+    rules: Vec<Rule>,
+    aliases: HashMap<String, String>,
+}
+
+impl Normalizer {
+    /// Constructs normalizer without rules
+    pub fn new() -> Normalizer {
+        Normalizer::default()
+    }
+
+    /// Constructs normalizer with rules for commonly seen dirty tags:
+    /// remaster notes, "feat." in titles and YouTube "Artist - Topic" channels
+    pub fn builtin() -> Normalizer {
+        static REMASTERED: &str = r"(?i)\s*(?:[\(\[](?:\d{4}\s+)?remaster(?:ed)?(?:\s+\d{4})?(?:\s+version)?[\)\]]|\s-\s(?:\d{4}\s+)?remaster(?:ed)?(?:\s+\d{4})?(?:\s+version)?$)";
+
+        Normalizer::new()
+            .rule(Rule::replace(Field::Name, REMASTERED, "").unwrap())
+            .rule(Rule::replace(Field::Album, REMASTERED, "").unwrap())
+            .rule(Rule::replace(Field::Artist, r"\s+-\s+Topic$", "").unwrap())
+            .rule(Rule::ExtractFeat)
+    }
+
+    /// Loads rules from given file
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<Normalizer> {
+        let path = path.as_ref();
+        let contents = fs::read_to_string(path)
+            .map_err(|e| invalid(format!("Can't read normalizer rules {}: {}", path.display(), e)))?;
+        Normalizer::parse(&contents).map_err(|e| invalid(format!("{}: {}", path.display(), e)))
+    }
+
+    /// Parses rules in file format
+    pub fn parse(rules: &str) -> Result<Normalizer> {
+        let mut normalizer = Normalizer::new();
+        for (n, line) in rules.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            normalizer = normalizer
+                .parse_line(line)
+                .map_err(|e| invalid(format!("line {}: {}", n + 1, e)))?;
+        }
+        Ok(normalizer)
+    }
+
+    fn parse_line(self, line: &str) -> Result<Normalizer> {
+        let (directive, rest) = match line.find(char::is_whitespace) {
+            Some(space) => (&line[..space], line[space..].trim_start()),
+            None => (line, ""),
+        };
+        let field = |name: &str| Field::parse(name).ok_or_else(|| invalid(format!("unknown field {}", name)));
+
+        match directive {
+            "feat" => Ok(self.rule(Rule::ExtractFeat)),
+            "titlecase" => Ok(self.rule(Rule::TitleCase(field(rest)?))),
+            "alias" => {
+                let eq = rest.find('=').ok_or_else(|| invalid("expected alias <from> = <to>".to_owned()))?;
+                Ok(self.alias(rest[..eq].trim(), rest[eq + 1..].trim()))
+            }
+            "replace" => {
+                let space = rest.find(char::is_whitespace).ok_or_else(|| invalid("missing pattern".to_owned()))?;
+                let field = field(&rest[..space])?;
+                let expr = rest[space..].trim_start();
+
+                let delimiter = expr.chars().next().ok_or_else(|| invalid("missing pattern".to_owned()))?;
+                let parts: Vec<&str> = expr[delimiter.len_utf8()..].split(delimiter).collect();
+                match parts.as_slice() {
+                    [pattern, replacement, ""] => Ok(self.rule(Rule::replace(field, pattern, replacement)?)),
+                    _ => Err(invalid(format!("expected {0}pattern{0}replacement{0}", delimiter))),
+                }
+            }
+            other => Err(invalid(format!("unknown rule {}", other))),
+        }
+    }
+
+    /// Appends a rule
+    pub fn rule(mut self, rule: Rule) -> Normalizer {
+        self.rules.push(rule);
+        self
+    }
+
+    /// Adds artist alias, it's applied to both artist and album artist
+    pub fn alias(mut self, from: &str, to: &str) -> Normalizer {
+        self.aliases.insert(from.to_lowercase(), to.to_owned());
+        self
+    }
+
+    /// Returns normalized copy of the track
+    pub fn normalize(&self, track: &Track) -> Track {
+        let mut track = track.clone();
+        for rule in &self.rules {
+            rule.apply(&mut track);
+        }
+
+        if let Some(alias) = self.aliases.get(&track.artist.to_lowercase()) {
+            track.artist = alias.clone();
+        }
+        if let Some(alias) = track.album_artist.as_ref().and_then(|a| self.aliases.get(&a.to_lowercase())) {
+            track.album_artist = Some(alias.clone());
+        }
+        track
+    }
+
+    /// Normalizes the track, keeping the original for auditing
+    pub fn audit(&self, track: &Track) -> Normalized {
+        Normalized {
+            original: track.clone(),
+            normalized: self.normalize(track),
+        }
+    }
+}
@@ -0,0 +1,107 @@
+use std::collections::VecDeque;
+use std::thread;
+use std::time::Duration;
+
+use crate::clock::MockClock;
+use crate::scrobbler::Track;
+
+// ----------------------------------------------------------------
+
+/// Player state change
+#[derive(Debug, Clone)]
+pub enum PlayerEvent {
+    /// New track started playing. Reported for repeats of the same track too.
+    TrackChanged(Track),
+    /// Playback of current track resumed
+    Play,
+    /// Playback of current track paused
+    Pause,
+    /// Playback position in current track changed to given offset
+    Seek(Duration),
+    /// Playback stopped, current track is over
+    Stop,
+}
+
+/// Source of player events, like a local player connection.
+///
+/// Sources are pulled by `scrobbler::Driver`, that turns events into scrobbler updates.
+pub trait PlayerSource {
+    /// Blocks until next player event, returns `None` once the source is closed
+    fn next_event(&mut self) -> Option<PlayerEvent>;
+}
+
+impl<S: PlayerSource + ?Sized> PlayerSource for Box<S> {
+    fn next_event(&mut self) -> Option<PlayerEvent> {
+        (**self).next_event()
+    }
+}
+
+// ----------------------------------------------------------------
+
+#[derive(Debug, Clone)]
+enum Step {
+    Event(PlayerEvent),
+    Wait(Duration),
+}
+
+/// In-memory player source, that replays a script of events and pauses between them.
+///
+/// Handy for tests and demos. Source made `with_clock()` advances the clock instead of
+/// sleeping, so minutes of playback are replayed instantly.
+///
+/// ## Example:
+/// ```
+/// let source = ScriptedSource::new()
+///     .track(Track::new("touching ii", "iamthemorning", 244))
+///     .wait(Duration::from_secs(30))
+///     .event(PlayerEvent::Pause)
+///     .event(PlayerEvent::Stop);
+/// ```
+#[derive(Debug, Clone, Default)]
+pub struct ScriptedSource {
+    steps: VecDeque<Step>,
+    clock: Option<MockClock>,
+}
+
+impl ScriptedSource {
+    /// Constructs empty script
+    pub fn new() -> ScriptedSource {
+        ScriptedSource { steps: VecDeque::new(), clock: None }
+    }
+
+    /// Constructs empty script, that waits by advancing given clock
+    pub fn with_clock(clock: MockClock) -> ScriptedSource {
+        ScriptedSource { steps: VecDeque::new(), clock: Some(clock) }
+    }
+
+    /// Appends an event
+    pub fn event(mut self, event: PlayerEvent) -> ScriptedSource {
+        self.steps.push_back(Step::Event(event));
+        self
+    }
+
+    /// Appends track change event
+    pub fn track(self, track: Track) -> ScriptedSource {
+        self.event(PlayerEvent::TrackChanged(track))
+    }
+
+    /// Appends a pause between events
+    pub fn wait(mut self, duration: Duration) -> ScriptedSource {
+        self.steps.push_back(Step::Wait(duration));
+        self
+    }
+}
+
+impl PlayerSource for ScriptedSource {
+    fn next_event(&mut self) -> Option<PlayerEvent> {
+        loop {
+            match self.steps.pop_front()? {
+                Step::Event(event) => return Some(event),
+                Step::Wait(duration) => match self.clock {
+                    Some(ref clock) => clock.advance(duration),
+                    None => thread::sleep(duration),
+                },
+            }
+        }
+    }
+}
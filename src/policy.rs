@@ -0,0 +1,192 @@
+use std::cmp::min;
+use std::collections::VecDeque;
+use std::time::Duration;
+
+use crate::scrobbler::Track;
+
+// ----------------------------------------------------------------
+
+/// Tracks shorter than that (in seconds) are never scrobbled by last.fm rules
+pub static LASTFM_MIN_TRACK_DURATION_SEC: u64 = 30;
+
+/// Track is scrobbled once it's been played for half its duration or for this long (in seconds)
+pub static LASTFM_MAX_SCROBBLE_THRESHOLD_SEC: u64 = 4 * 60;
+
+/// Number of past plays kept in `History`
+pub static HISTORY_LEN: usize = 50;
+
+// ----------------------------------------------------------------
+
+/// Single play of a track, as seen by the timer
+#[derive(Debug, Clone)]
+pub struct Play {
+    /// Played track, its timestamp is set to the play start
+    pub track: Track,
+    /// Time spent playing, up to the last player event
+    pub played: Duration,
+    /// Whether playback was moved forward by a seek
+    pub skipped: bool,
+    /// Whether the play was scrobbled
+    pub scrobbled: bool,
+}
+
+impl Play {
+    pub(crate) fn new(track: Track) -> Play {
+        Play {
+            track,
+            played: Duration::from_secs(0),
+            skipped: false,
+            scrobbled: false,
+        }
+    }
+}
+
+/// Recent plays, newest first
+#[derive(Debug, Clone, Default)]
+pub struct History {
+    plays: VecDeque<Play>,
+}
+
+impl History {
+    pub fn new() -> History {
+        History { plays: VecDeque::new() }
+    }
+
+    /// Iterates over past plays, newest first
+    pub fn plays(&self) -> impl Iterator<Item = &Play> {
+        self.plays.iter()
+    }
+
+    /// Returns the most recent scrobbled play of given track, if any
+    pub fn last_scrobble_of(&self, track: &Track) -> Option<&Play> {
+        self.plays.iter().find(|p| p.scrobbled && p.track.is_same(track))
+    }
+
+    pub(crate) fn push(&mut self, play: Play) {
+        self.plays.push_front(play);
+        self.plays.truncate(HISTORY_LEN);
+    }
+}
+
+// ----------------------------------------------------------------
+
+/// Decides when (and whether) current play is scrobbled.
+///
+/// Consulted by the scrobbler timer every time the play changes: when it starts,
+/// is paused, resumed or seeked.
+pub trait ScrobblePolicy: Send + Sync {
+    /// Returns how long the track has to be played to be scrobbled, `None` means never
+    fn threshold(&self, play: &Play, history: &History) -> Option<Duration>;
+}
+
+type Filter = Box<dyn Fn(&Play, &History) -> bool + Send + Sync>;
+
+/// Configurable scrobble rules, last.fm ones by default:
+/// tracks of 30 seconds and longer are scrobbled once played for half
+/// their duration or for 4 minutes, whichever comes first.
+///
+/// ## Example:
+/// ```
+/// // 80% of any track, nothing that was skipped through or repeated within an hour
+/// let rules = Rules::lastfm()
+///     .fraction(0.8)
+///     .max_threshold(None)
+///     .no_scrobble_after_skip()
+///     .repeat_cooldown(Duration::from_secs(60 * 60));
+/// ```
+pub struct Rules {
+    fraction: f64,
+    max_threshold: Option<Duration>,
+    min_duration: Duration,
+    skip: bool,
+    cooldown: Option<Duration>,
+    filters: Vec<Filter>,
+}
+
+impl Rules {
+    /// Constructs last.fm rules
+    pub fn lastfm() -> Rules {
+        Rules {
+            fraction: 0.5,
+            max_threshold: Some(Duration::from_secs(LASTFM_MAX_SCROBBLE_THRESHOLD_SEC)),
+            min_duration: Duration::from_secs(LASTFM_MIN_TRACK_DURATION_SEC),
+            skip: false,
+            cooldown: None,
+            filters: Vec::new(),
+        }
+    }
+
+    /// Sets part of the track (0 to 1), that has to be played
+    pub fn fraction(mut self, fraction: f64) -> Rules {
+        self.fraction = fraction.max(0.0).min(1.0);
+        self
+    }
+
+    /// Sets play time, that is enough to scrobble a track regardless of its duration
+    pub fn max_threshold(mut self, threshold: Option<Duration>) -> Rules {
+        self.max_threshold = threshold;
+        self
+    }
+
+    /// Sets minimal track duration, shorter tracks are not scrobbled
+    pub fn min_duration(mut self, duration: Duration) -> Rules {
+        self.min_duration = duration;
+        self
+    }
+
+    /// Disables scrobbling of plays, that were moved forward by a seek
+    pub fn no_scrobble_after_skip(mut self) -> Rules {
+        self.skip = true;
+        self
+    }
+
+    /// Disables scrobbling of a track, if it was scrobbled less than given time ago
+    pub fn repeat_cooldown(mut self, cooldown: Duration) -> Rules {
+        self.cooldown = Some(cooldown);
+        self
+    }
+
+    /// Adds custom predicate, plays it rejects are not scrobbled
+    pub fn filter<F>(mut self, filter: F) -> Rules
+    where
+        F: Fn(&Play, &History) -> bool + Send + Sync + 'static,
+    {
+        self.filters.push(Box::new(filter));
+        self
+    }
+
+    fn in_cooldown(&self, play: &Play, history: &History) -> bool {
+        let (cooldown, previous) = match (self.cooldown, history.last_scrobble_of(&play.track)) {
+            (Some(cooldown), Some(previous)) => (cooldown, previous),
+            _ => return false,
+        };
+        match (play.track.timestamp_utc, previous.track.timestamp_utc) {
+            (Some(now), Some(then)) => (now.saturating_sub(then) as u64) < cooldown.as_secs(),
+            _ => false,
+        }
+    }
+}
+
+impl Default for Rules {
+    fn default() -> Rules {
+        Rules::lastfm()
+    }
+}
+
+impl ScrobblePolicy for Rules {
+    fn threshold(&self, play: &Play, history: &History) -> Option<Duration> {
+        let duration = Duration::from_secs(play.track.duration_sec as u64);
+        if duration < self.min_duration || (self.skip && play.skipped) || self.in_cooldown(play, history) {
+            return None;
+        }
+        if !self.filters.iter().all(|f| f(play, history)) {
+            return None;
+        }
+
+        let threshold = duration.mul_f64(self.fraction);
+        Some(match self.max_threshold {
+            Some(max) => min(threshold, max),
+            None => threshold,
+        })
+    }
+}
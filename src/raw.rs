@@ -0,0 +1,180 @@
+use std::io::ErrorKind as IoErrorKind;
+
+use url::Url;
+
+use serde_json::Value;
+
+use lastfm::error::{Error as LastfmError, ApiError};
+
+use crate::client::Client;
+use crate::utils::{Error, Result};
+
+// ----------------------------------------------------------------
+
+/// Computes last.fm API method signature.
+///
+/// All parameters except `format` and `callback` are ordered by name
+/// and concatenated as `<name><value>`, then shared secret is appended
+/// and md5 hash of the resulting string is returned as a hex string.
+///
+/// Check https://www.last.fm/api/authspec#_8-signing-calls for details.
+pub fn api_sig<K, V>(params: &[(K, V)], secret: &str) -> String
+where
+    K: AsRef<str>,
+    V: AsRef<str>,
+{
+    let mut sorted: Vec<(&str, &str)> = params
+        .iter()
+        .map(|(k, v)| (k.as_ref(), v.as_ref()))
+        .filter(|&(k, _)| k != "format" && k != "callback")
+        .collect();
+    sorted.sort();
+
+    let mut raw = String::new();
+    for (k, v) in sorted {
+        raw.push_str(k);
+        raw.push_str(v);
+    }
+    raw.push_str(secret);
+
+    format!("{:x}", md5::compute(raw.as_bytes()))
+}
+
+/// Checks whether decoded response is a last.fm error payload
+/// (`{"error": N, "message": "..."}`) and turns it into `Error::Lastfm` with the code set if so.
+pub fn check_api_error(value: &Value) -> Result<()> {
+    let code = match value.get("error").and_then(Value::as_u64) {
+        Some(code) => code as u32,
+        None => return Ok(()),
+    };
+    let api: ApiError = serde_json::from_value(value.clone()).map_err(
+        |e| Error::io(IoErrorKind::InvalidData, e),
+    )?;
+    Err(Error::api(code, LastfmError::from(api)))
+}
+
+/// Returns last.fm error of the response body, if it's an error payload.
+///
+/// Meant for the failure path of decoders, so successful responses are parsed only once.
+pub(crate) fn api_error(body: &str) -> Option<Error> {
+    let value = serde_json::from_str::<Value>(body).ok()?;
+    check_api_error(&value).err()
+}
+
+fn decode(body: &str) -> Result<Value> {
+    let value = serde_json::from_str(body).map_err(
+        |e| Error::io(IoErrorKind::InvalidData, e),
+    )?;
+    check_api_error(&value)?;
+    Ok(value)
+}
+
+// ----------------------------------------------------------------
+
+/// Raw request builder
+///
+/// Allows to call API methods that are not modelled by `lastfm_parse_rs`.
+/// Method name and parameters are passed as is, client adds API key and response format.
+///
+/// Signed requests also get a session key (if client is authenticated)
+/// and `api_sig`; they are sent as POST, unsigned ones are sent as GET.
+///
+/// `send()` resolves into the response body as is, it's left for the caller to decode.
+/// `send_json()` goes through the same error detection as typed requests:
+/// last.fm error payloads are reported as `Error::Lastfm`.
+///
+/// ## Example:
+/// ```
+/// let body = client.raw_request("artist.getCorrection")
+///     .param("artist", "guns and roses")
+///     .send_json()
+///     .await;
+///
+/// println!("Result: {:?}", body);
+/// ```
+pub struct RawRequest<'c> {
+    client: &'c Client,
+    method: String,
+    params: Vec<(String, String)>,
+    signed: bool,
+    refresh: bool,
+}
+
+impl<'c> RawRequest<'c> {
+    /// Constructs new raw request for given method name (like `track.love`)
+    pub fn new(client: &'c Client, method: &str) -> RawRequest<'c> {
+        RawRequest {
+            client,
+            method: method.to_owned(),
+            params: Vec::new(),
+            signed: false,
+            refresh: false,
+        }
+    }
+
+    /// Adds method parameter
+    pub fn param(mut self, name: &str, value: &str) -> RawRequest<'c> {
+        self.params.push((name.to_owned(), value.to_owned()));
+        self
+    }
+
+    /// Adds a bunch of method parameters
+    pub fn params<I, K, V>(mut self, params: I) -> RawRequest<'c>
+    where
+        I: IntoIterator<Item = (K, V)>,
+        K: AsRef<str>,
+        V: AsRef<str>,
+    {
+        self.params.extend(params.into_iter().map(|(k, v)| {
+            (k.as_ref().to_owned(), v.as_ref().to_owned())
+        }));
+        self
+    }
+
+    /// Marks the request as signed (required for `auth` and `write` methods)
+    pub fn signed(mut self, signed: bool) -> RawRequest<'c> {
+        self.signed = signed;
+        self
+    }
+
+    /// Bypasses response cache lookup for this request (has no effect if cache is disabled)
+    pub fn refresh(mut self, refresh: bool) -> RawRequest<'c> {
+        self.refresh = refresh;
+        self
+    }
+
+    /// Returns full request url, including api key, session key and signature
+    pub fn url(&self) -> Result<Url> {
+        let mut pairs = self.params.clone();
+        pairs.push(("method".to_owned(), self.method.clone()));
+        pairs.push(("api_key".to_owned(), self.client.api_key().to_owned()));
+
+        if self.signed {
+            if let Some(sk) = self.client.session() {
+                pairs.push(("sk".to_owned(), sk.to_owned()));
+            }
+            let sig = self.client.api_sig(&pairs)?;
+            pairs.push(("api_sig".to_owned(), sig));
+        }
+        pairs.push(("format".to_owned(), "json".to_owned()));
+
+        let mut url = self.client.base_url().clone();
+        url.query_pairs_mut().extend_pairs(pairs.iter());
+        Ok(url)
+    }
+
+    /// Sends the request and resolves into raw response body.
+    ///
+    /// Body is not decoded, so last.fm error payloads are not detected here.
+    pub async fn send(self) -> Result<String> {
+        let url = self.url()?;
+        self.client.fetch(url, self.signed, self.refresh).await
+    }
+
+    /// Sends the request and resolves into dynamic JSON value
+    pub async fn send_json(self) -> Result<Value> {
+        let url = self.url()?;
+        let body = self.client.fetch(url, self.signed, self.refresh).await?;
+        decode(&body)
+    }
+}
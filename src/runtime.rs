@@ -0,0 +1,348 @@
+use std::future::Future;
+use std::io::{ErrorKind as IoErrorKind, Result as IoResult};
+use std::net::SocketAddr;
+use std::pin::Pin;
+use std::sync::Arc;
+use std::time::Duration;
+
+use futures::channel::oneshot;
+use futures::io::{AsyncRead, AsyncWrite};
+
+use crate::utils::{Error, Result};
+
+// ----------------------------------------------------------------
+
+/// Connected byte stream, as returned by `Runtime::connect()`
+pub trait Stream: AsyncRead + AsyncWrite + Unpin + Send {}
+
+impl<S: AsyncRead + AsyncWrite + Unpin + Send> Stream for S {}
+
+/// Detached task, spawned on a runtime
+pub type Task = Pin<Box<dyn Future<Output = ()> + Send + 'static>>;
+
+/// Future of a timer, resolves once the time is up
+pub type Sleep = Pin<Box<dyn Future<Output = ()> + Send + 'static>>;
+
+/// Future of a TCP connection
+pub type Connect = Pin<Box<dyn Future<Output = IoResult<Box<dyn Stream>>> + Send + 'static>>;
+
+/// Async runtime, that client and scrobbler run on.
+///
+/// Client only needs a few things from a runtime: spawning tasks, timers and
+/// TCP connections, so it's easy to plug in a new one. Implementations for Tokio,
+/// async-std and a blocking thread pool live behind `tokio`, `async-std` and
+/// `thread-pool` cargo features respectively.
+///
+/// Client uses `default()` runtime unless other one is set with `Builder::runtime()`.
+pub trait Runtime: Send + Sync {
+    /// Spawns detached task
+    fn spawn(&self, task: Task);
+
+    /// Returns timer future, that resolves after given duration
+    fn sleep(&self, duration: Duration) -> Sleep;
+
+    /// Opens TCP connection to given address
+    fn connect(&self, addr: SocketAddr) -> Connect;
+
+    /// Runs the future to completion, blocking current thread.
+    ///
+    /// Must not be called from within asynchronous code. Use `block_on()` function
+    /// to run futures that resolve to a value.
+    fn block_on(&self, task: Pin<Box<dyn Future<Output = ()> + '_>>);
+}
+
+// ----------------------------------------------------------------
+
+/// Runs the future on given runtime to completion and returns its output
+pub fn block_on<F: Future>(runtime: &dyn Runtime, future: F) -> F::Output {
+    let mut output = None;
+    runtime.block_on(Box::pin(async {
+        output = Some(future.await);
+    }));
+    output.expect("Runtime::block_on() returned before the future completed")
+}
+
+/// Spawns the future on given runtime and returns a future of its output
+pub fn spawn_with_result<F>(runtime: &dyn Runtime, future: F) -> impl Future<Output = Result<F::Output>> + Send
+where
+    F: Future + Send + 'static,
+    F::Output: Send + 'static,
+{
+    let (tx, rx) = oneshot::channel();
+    runtime.spawn(Box::pin(async move {
+        let _ = tx.send(future.await);
+    }));
+    async move {
+        rx.await.map_err(|_| Error::io(IoErrorKind::Interrupted, "Spawned task was cancelled"))
+    }
+}
+
+/// Returns runtime for enabled cargo features, that isn't shared with other clients.
+///
+/// Tokio and thread pool runtimes are started anew, async-std has only a global one.
+pub fn dedicated() -> Result<Arc<dyn Runtime>> {
+    #[cfg(feature = "tokio")]
+    return Ok(Arc::new(TokioRuntime::dedicated()?));
+
+    #[cfg(all(feature = "async-std", not(feature = "tokio")))]
+    return Ok(Arc::new(AsyncStdRuntime));
+
+    #[cfg(all(feature = "thread-pool", not(any(feature = "tokio", feature = "async-std"))))]
+    return Ok(Arc::new(ThreadPoolRuntime::new()?));
+
+    #[cfg(not(any(feature = "tokio", feature = "async-std", feature = "thread-pool")))]
+    return Err(Error::build("No runtime available: enable `tokio`, `async-std` or `thread-pool` feature"));
+}
+
+/// Shared runtime is a runtime too, so `Arc<dyn Runtime>` can be passed to `Builder::runtime()`
+impl<R: Runtime + ?Sized> Runtime for Arc<R> {
+    fn spawn(&self, task: Task) {
+        (**self).spawn(task)
+    }
+
+    fn sleep(&self, duration: Duration) -> Sleep {
+        (**self).sleep(duration)
+    }
+
+    fn connect(&self, addr: SocketAddr) -> Connect {
+        (**self).connect(addr)
+    }
+
+    fn block_on(&self, task: Pin<Box<dyn Future<Output = ()> + '_>>) {
+        (**self).block_on(task)
+    }
+}
+
+/// Returns runtime for enabled cargo features.
+///
+/// Tokio runtime is preferred, then async-std, then the thread pool.
+pub fn default() -> Result<Arc<dyn Runtime>> {
+    #[cfg(feature = "tokio")]
+    return Ok(Arc::new(TokioRuntime::new()));
+
+    #[cfg(all(feature = "async-std", not(feature = "tokio")))]
+    return Ok(Arc::new(AsyncStdRuntime));
+
+    #[cfg(all(feature = "thread-pool", not(any(feature = "tokio", feature = "async-std"))))]
+    return Ok(Arc::new(ThreadPoolRuntime::new()?));
+
+    #[cfg(not(any(feature = "tokio", feature = "async-std", feature = "thread-pool")))]
+    return Err(Error::build("No runtime available: enable `tokio`, `async-std` or `thread-pool` feature"));
+}
+
+#[allow(dead_code)]
+fn stream<S: Stream + 'static>(stream: IoResult<S>) -> IoResult<Box<dyn Stream>> {
+    stream.map(|s| Box::new(s) as Box<dyn Stream>)
+}
+
+// ----------------------------------------------------------------
+
+#[cfg(feature = "tokio")]
+pub use self::tokio_runtime::TokioRuntime;
+
+#[cfg(feature = "tokio")]
+mod tokio_runtime {
+    use std::io::Error as IoError;
+    use std::sync::OnceLock;
+
+    use tokio::net::TcpStream;
+    use tokio::runtime::{Builder as RuntimeBuilder, Handle, Runtime as Tokio};
+    use tokio_util::compat::TokioAsyncReadCompatExt;
+
+    use super::*;
+
+    /// Runtime used outside of Tokio contexts, when no handle was given
+    static BACKGROUND: OnceLock<Tokio> = OnceLock::new();
+
+    fn background() -> &'static Tokio {
+        BACKGROUND.get_or_init(|| {
+            RuntimeBuilder::new_multi_thread()
+                .thread_name("first-fm-tokio")
+                .enable_all()
+                .build()
+                .expect("Failed to start background Tokio runtime")
+        })
+    }
+
+    /// Tokio runtime
+    ///
+    /// By default tasks, timers and connections are bound to the runtime of the calling task.
+    /// When called outside of Tokio, a shared background runtime is started and used instead.
+    #[derive(Clone, Default)]
+    pub struct TokioRuntime {
+        handle: Option<Handle>,
+        owned: Option<Arc<Owned>>,
+    }
+
+    /// Tokio runtime owned by `TokioRuntime` clones, it's shut down when the last one is dropped
+    struct Owned(Option<Tokio>);
+
+    impl Drop for Owned {
+        fn drop(&mut self) {
+            // plain drop panics when the last clone goes away inside async code
+            if let Some(tokio) = self.0.take() {
+                tokio.shutdown_background();
+            }
+        }
+    }
+
+    impl TokioRuntime {
+        /// Constructs runtime that follows calling task's Tokio runtime
+        pub fn new() -> TokioRuntime {
+            TokioRuntime { handle: None, owned: None }
+        }
+
+        /// Constructs runtime, that always uses given Tokio runtime
+        pub fn with_handle(handle: Handle) -> TokioRuntime {
+            TokioRuntime { handle: Some(handle), owned: None }
+        }
+
+        /// Starts a dedicated multi-threaded Tokio runtime, that is shut down along with the last clone
+        pub fn dedicated() -> Result<TokioRuntime> {
+            let tokio = RuntimeBuilder::new_multi_thread()
+                .thread_name("first-fm-tokio")
+                .enable_all()
+                .build()
+                .map_err(Error::build)?;
+            Ok(TokioRuntime {
+                handle: Some(tokio.handle().clone()),
+                owned: Some(Arc::new(Owned(Some(tokio)))),
+            })
+        }
+
+        fn handle(&self) -> Handle {
+            self.handle
+                .clone()
+                .or_else(|| Handle::try_current().ok())
+                .unwrap_or_else(|| background().handle().clone())
+        }
+    }
+
+    impl Runtime for TokioRuntime {
+        fn spawn(&self, task: Task) {
+            self.handle().spawn(task);
+        }
+
+        fn sleep(&self, duration: Duration) -> Sleep {
+            let handle = self.handle();
+            let _entered = handle.enter();
+            Box::pin(tokio::time::sleep(duration))
+        }
+
+        fn connect(&self, addr: SocketAddr) -> Connect {
+            let connecting = self.handle().spawn(TcpStream::connect(addr));
+            Box::pin(async move {
+                let tcp = connecting.await.map_err(|e| IoError::new(IoErrorKind::Other, e))?;
+                stream(tcp.map(|s| s.compat()))
+            })
+        }
+
+        fn block_on(&self, task: Pin<Box<dyn Future<Output = ()> + '_>>) {
+            match self.handle {
+                Some(ref handle) => handle.block_on(task),
+                None => background().block_on(task),
+            }
+        }
+    }
+}
+
+// ----------------------------------------------------------------
+
+#[cfg(feature = "async-std")]
+pub use self::async_std_runtime::AsyncStdRuntime;
+
+#[cfg(feature = "async-std")]
+mod async_std_runtime {
+    use async_std::net::TcpStream;
+    use async_std::task;
+
+    use super::*;
+
+    /// async-std runtime
+    #[derive(Clone, Copy, Default)]
+    pub struct AsyncStdRuntime;
+
+    impl Runtime for AsyncStdRuntime {
+        fn spawn(&self, task: Task) {
+            task::spawn(task);
+        }
+
+        fn sleep(&self, duration: Duration) -> Sleep {
+            Box::pin(task::sleep(duration))
+        }
+
+        fn connect(&self, addr: SocketAddr) -> Connect {
+            Box::pin(async move { stream(TcpStream::connect(addr).await) })
+        }
+
+        fn block_on(&self, task: Pin<Box<dyn Future<Output = ()> + '_>>) {
+            task::block_on(task)
+        }
+    }
+}
+
+// ----------------------------------------------------------------
+
+#[cfg(feature = "thread-pool")]
+pub use self::thread_pool_runtime::ThreadPoolRuntime;
+
+#[cfg(feature = "thread-pool")]
+mod thread_pool_runtime {
+    use std::net::TcpStream;
+
+    use async_io::{Async, Timer};
+    use futures::executor::ThreadPool;
+
+    use super::*;
+
+    /// Blocking thread pool runtime
+    ///
+    /// Tasks are run on a fixed pool of threads, timers and sockets are driven by
+    /// `async-io` reactor thread. Doesn't need any async runtime to be set up by the application.
+    #[derive(Clone)]
+    pub struct ThreadPoolRuntime {
+        pool: ThreadPool,
+    }
+
+    impl ThreadPoolRuntime {
+        /// Constructs runtime with a thread per CPU core
+        pub fn new() -> Result<ThreadPoolRuntime> {
+            let pool = ThreadPool::builder()
+                .name_prefix("first-fm-pool-")
+                .create()
+                .map_err(Error::build)?;
+            Ok(ThreadPoolRuntime { pool })
+        }
+
+        /// Constructs runtime with given number of threads
+        pub fn with_threads(threads: usize) -> Result<ThreadPoolRuntime> {
+            let pool = ThreadPool::builder()
+                .name_prefix("first-fm-pool-")
+                .pool_size(threads)
+                .create()
+                .map_err(Error::build)?;
+            Ok(ThreadPoolRuntime { pool })
+        }
+    }
+
+    impl Runtime for ThreadPoolRuntime {
+        fn spawn(&self, task: Task) {
+            self.pool.spawn_ok(task);
+        }
+
+        fn sleep(&self, duration: Duration) -> Sleep {
+            let timer = Timer::after(duration);
+            Box::pin(async move {
+                timer.await;
+            })
+        }
+
+        fn connect(&self, addr: SocketAddr) -> Connect {
+            Box::pin(async move { stream(Async::<TcpStream>::connect(addr).await) })
+        }
+
+        fn block_on(&self, task: Pin<Box<dyn Future<Output = ()> + '_>>) {
+            async_io::block_on(task)
+        }
+    }
+}
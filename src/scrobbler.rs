@@ -1,20 +1,36 @@
-use std::collections::VecDeque;
+use std::collections::{HashMap, VecDeque};
+use std::fs::{self, File};
+use std::future::Future;
+use std::io::{BufRead, BufReader, ErrorKind as IoErrorKind, Write};
 use std::ops::Drop;
-use std::convert::TryFrom;
+use std::path::{Path, PathBuf};
+use std::result::Result as StdResult;
 use std::sync::{Arc, Mutex};
-use std::sync::mpsc::{channel, Sender};
-use std::thread::{spawn, JoinHandle};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::{channel, Sender, Receiver, RecvTimeoutError};
+use std::thread::{self, spawn, JoinHandle};
+use std::time::{Duration, Instant};
 
-use tokio_core::reactor::Core;
+use futures::StreamExt;
+use futures::channel::mpsc::{unbounded, UnboundedSender, UnboundedReceiver};
+use futures::channel::oneshot;
+use futures::future::{select, Either};
 
-use lastfm::track::ScrobbleTrack;
+use serde_json::{json, Value};
 
-use client::{Client, Builder};
-use utils::{Error, Result};
+use crate::client::Client;
+use crate::clock::{Clock, SystemClock};
+use crate::normalize::Normalizer;
+use crate::policy::{History, Play, Rules, ScrobblePolicy};
+use crate::player::{PlayerEvent, PlayerSource};
+use crate::runtime::{self, Runtime};
+use crate::utils::{Error, Result};
+use crate::sink::{Lastfm, ScrobbleSink};
+use crate::write::{Correction, Submission};
 
 // ----------------------------------------------------------------
 
-#[derive(Debug, Hash)]
+#[derive(Debug, Clone, Hash)]
 pub struct Track {
     pub name: String,
     pub artist: String,
@@ -22,7 +38,15 @@ pub struct Track {
     pub album: Option<String>,
     pub album_artist: Option<String>,
     pub track_number: Option<u32>,
-    timestamp_utc: Option<u32>,
+    /// MusicBrainz recording id
+    pub mbid: Option<String>,
+    /// Whether user chose the track, as opposed to radio or recommendations (last.fm assumes so)
+    pub chosen_by_user: Option<bool>,
+    /// Sub-client version, like the name of a player plugin
+    pub context: Option<String>,
+    /// Stream id, for tracks played from last.fm radio
+    pub stream_id: Option<String>,
+    pub(crate) timestamp_utc: Option<u32>,
 }
 
 impl Track {
@@ -30,10 +54,14 @@ impl Track {
         Track {
             name: name.to_owned(),
             artist: artist.to_owned(),
-            duration_sec: duration_sec,
+            duration_sec,
             album: None,
             album_artist: None,
             track_number: None,
+            mbid: None,
+            chosen_by_user: None,
+            context: None,
+            stream_id: None,
             timestamp_utc: None,
         }
     }
@@ -52,51 +80,311 @@ impl Track {
         self.track_number = Some(track_number);
         self
     }
+
+    pub fn mbid(mut self, mbid: &str) -> Track {
+        self.mbid = Some(mbid.to_owned());
+        self
+    }
+
+    pub fn chosen_by_user(mut self, chosen_by_user: bool) -> Track {
+        self.chosen_by_user = Some(chosen_by_user);
+        self
+    }
+
+    pub fn context(mut self, context: &str) -> Track {
+        self.context = Some(context.to_owned());
+        self
+    }
+
+    pub fn stream_id(mut self, stream_id: &str) -> Track {
+        self.stream_id = Some(stream_id.to_owned());
+        self
+    }
+
+    /// Sets play start time (unix, UTC) for offline or backdated plays.
+    ///
+    /// Plays followed by the scrobbler are stamped when they start, so this is
+    /// only needed for tracks passed to `Scrobbler::scrobble()` or `Client::scrobble()`.
+    pub fn timestamp(mut self, timestamp_utc: u32) -> Track {
+        self.timestamp_utc = Some(timestamp_utc);
+        self
+    }
+
+    /// Returns play start time (unix, UTC), if it's set
+    pub fn timestamp_utc(&self) -> Option<u32> {
+        self.timestamp_utc
+    }
 }
 
-impl TryFrom<Track> for ScrobbleTrack {
-    type Error = Error;
-    fn try_from(value: Track) -> Result<ScrobbleTrack> {
-        let ts = value.timestamp_utc.ok_or(Error::build("no scrobble timestamp set"))?;
-        let tr = ScrobbleTrack::new(
-            value.artist,
-            value.name,
-            ts,
-        );
-        tr.
+impl Track {
+    pub(crate) fn is_same(&self, other: &Track) -> bool {
+        self.name == other.name && self.artist == other.artist && self.album == other.album
+    }
+
+    fn to_json(&self) -> Value {
+        json!({
+            "name": self.name,
+            "artist": self.artist,
+            "duration_sec": self.duration_sec,
+            "album": self.album,
+            "album_artist": self.album_artist,
+            "track_number": self.track_number,
+            "mbid": self.mbid,
+            "chosen_by_user": self.chosen_by_user,
+            "context": self.context,
+            "stream_id": self.stream_id,
+            "timestamp_utc": self.timestamp_utc,
+        })
+    }
+
+    fn from_json(value: &Value) -> Option<Track> {
+        let string = |key: &str| value[key].as_str().map(|s| s.to_owned());
+        let number = |key: &str| value[key].as_u64().map(|n| n as u32);
+
+        Some(Track {
+            name: string("name")?,
+            artist: string("artist")?,
+            duration_sec: number("duration_sec")?,
+            album: string("album"),
+            album_artist: string("album_artist"),
+            track_number: number("track_number"),
+            mbid: string("mbid"),
+            chosen_by_user: value["chosen_by_user"].as_bool(),
+            context: string("context"),
+            stream_id: string("stream_id"),
+            timestamp_utc: number("timestamp_utc"),
+        })
     }
 }
 
 // ----------------------------------------------------------------
 
-type Cache = Arc<Mutex<VecDeque<Track>>>;
+/// Cached scrobbles within this many seconds of each other are considered duplicates by default
+pub static DEFAULT_DEDUP_WINDOW_SEC: u64 = 30;
 
-struct Essentials {
-    core: Core,
-    client: Client,
-    cache: Cache,
+/// Delay before the first retry after a temporary submission failure (in seconds),
+/// it's doubled with every failure that follows
+pub static RETRY_MIN_DELAY_SEC: u64 = 30;
+
+/// Max delay between submission retries (in seconds)
+pub static RETRY_MAX_DELAY_SEC: u64 = 30 * 60;
+
+/// last.fm errors, that mean the request itself is bad: 6 "invalid parameters",
+/// 7 "invalid resource specified" and 13 "invalid method signature"
+static REJECTED_API_ERRORS: &[u32] = &[6, 7, 13];
+
+/// last.fm error 9: "Invalid session key - Please re-authenticate"
+static INVALID_SESSION_API_ERROR: u32 = 9;
+
+/// Cached scrobble, waiting to be submitted
+#[derive(Debug, Clone)]
+pub struct Pending {
+    /// Id, that stays the same across restarts (with cache file)
+    pub id: u64,
+    pub track: Track,
+    /// Number of submission attempts so far
+    pub attempts: u32,
+    /// Error of the last failed attempt
+    pub last_error: Option<String>,
+    /// Submission failed in a way that doesn't tell whether last.fm got the track
+    pub uncertain: bool,
+}
+
+impl Pending {
+    pub(crate) fn new(id: u64, track: Track) -> Pending {
+        Pending {
+            id,
+            track,
+            attempts: 0,
+            last_error: None,
+            uncertain: false,
+        }
+    }
+
+    fn to_json(&self) -> Value {
+        let mut value = self.track.to_json();
+        value["id"] = json!(self.id);
+        value["attempts"] = json!(self.attempts);
+        value["last_error"] = json!(self.last_error);
+        if self.uncertain {
+            value["uncertain"] = Value::Bool(true);
+        }
+        value
+    }
+
+    /// Parses cached scrobble, ones saved without an id get it from `next_id`
+    fn from_json(value: &Value, next_id: &mut u64) -> Option<Pending> {
+        let id = value["id"].as_u64().unwrap_or(*next_id);
+        *next_id = (*next_id).max(id + 1);
+        Some(Pending {
+            id,
+            track: Track::from_json(value)?,
+            attempts: value["attempts"].as_u64().unwrap_or(0) as u32,
+            last_error: value["last_error"].as_str().map(|e| e.to_owned()),
+            uncertain: value["uncertain"].as_bool().unwrap_or(false),
+        })
+    }
+}
+
+/// Checks whether both tracks are the same scrobble: same artist and name,
+/// timestamps no more than `window` apart
+fn is_duplicate(a: &Track, b: &Track, window: Duration) -> bool {
+    let same_tags = a.artist.to_lowercase() == b.artist.to_lowercase()
+        && a.name.to_lowercase() == b.name.to_lowercase();
+    match (a.timestamp_utc, b.timestamp_utc) {
+        (Some(x), Some(y)) => same_tags && (x.max(y) - x.min(y)) as u64 <= window.as_secs(),
+        _ => false,
+    }
+}
+
+/// Appends the track to the cache unless it's already there, returns `false` if it's a duplicate
+fn push_unique(cache: &mut VecDeque<Pending>, entry: Pending, window: Duration) -> bool {
+    if cache.iter().any(|e| is_duplicate(&e.track, &entry.track, window)) {
+        debug!("Skipping duplicate scrobble of {} - {}", entry.track.artist, entry.track.name);
+        return false;
+    }
+    cache.push_back(entry);
+    true
+}
+
+type Cache = Arc<Mutex<VecDeque<Pending>>>;
+
+/// Loads cached tracks from a file (one JSON object per line), missing file means empty cache.
+/// Returns them along with the next free id.
+pub(crate) fn load_cache(path: &Path) -> Result<(VecDeque<Pending>, u64)> {
+    let file = match File::open(path) {
+        Ok(file) => file,
+        Err(ref e) if e.kind() == IoErrorKind::NotFound => return Ok((VecDeque::new(), 0)),
+        Err(e) => return Err(e.into()),
+    };
+
+    let mut tracks = VecDeque::new();
+    let mut next_id = 0;
+    for line in BufReader::new(file).lines() {
+        let line = line?;
+        if line.trim().is_empty() {
+            continue;
+        }
+        let value = serde_json::from_str::<Value>(&line).ok();
+        match value.as_ref().and_then(|v| Pending::from_json(v, &mut next_id)) {
+            Some(entry) => tracks.push_back(entry),
+            None => warn!("Skipping malformed cached scrobble: {}", line),
+        }
+    }
+    Ok((tracks, next_id))
+}
+
+/// Saves cached tracks to a file, replacing it atomically.
+///
+/// Temp file is `<cache file>.tmp`, so caches that only differ in extension
+/// (like `scrobbles.1` and `scrobbles.2`) don't share it.
+pub(crate) fn save_cache(path: &Path, tracks: &VecDeque<Pending>) -> Result<()> {
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir)?;
+    }
+
+    let mut tmp = path.as_os_str().to_owned();
+    tmp.push(".tmp");
+    let tmp = PathBuf::from(tmp);
+    {
+        let mut file = File::create(&tmp)?;
+        for entry in tracks {
+            writeln!(file, "{}", entry.to_json())?;
+        }
+        file.sync_all()?;
+    }
+    fs::rename(&tmp, path)?;
+    Ok(())
 }
 
-impl Essentials {
-    fn new(client_config: Builder, cache: &Cache) -> Result<Essentials> {
-        let core = Core::new().map_err(Error::build)?;
-        let builder = client_config.handle(core.handle());
+// ----------------------------------------------------------------
+
+type CorrectionKey = (String, String, Option<String>);
+
+/// Corrections last.fm made to submitted tracks, keyed by submitted artist, name and album.
+///
+/// Players can use it to fix their libraries, scrobbler can apply it to later plays
+/// of the same tracks before submission (see `Builder::learn_corrections()`).
+#[derive(Debug, Clone, Default)]
+pub struct Corrections {
+    map: HashMap<CorrectionKey, Correction>,
+}
+
+impl Corrections {
+    fn key(track: &Track) -> CorrectionKey {
+        (track.artist.clone(), track.name.clone(), track.album.clone())
+    }
+
+    /// Returns correction, that last.fm made to a track with the same tags
+    pub fn get(&self, track: &Track) -> Option<&Correction> {
+        self.map.get(&Corrections::key(track))
+    }
+
+    /// Iterates over submitted `(artist, name, album)` tags and their corrections
+    pub fn iter(&self) -> impl Iterator<Item = ((&str, &str, Option<&str>), &Correction)> {
+        self.map
+            .iter()
+            .map(|((artist, name, album), c)| ((artist.as_str(), name.as_str(), album.as_deref()), c))
+    }
+
+    pub fn len(&self) -> usize {
+        self.map.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.map.is_empty()
+    }
+
+    /// Returns the track with learned correction applied
+    pub fn apply(&self, mut track: Track) -> Track {
+        if let Some(correction) = self.get(&track) {
+            correction.apply(&mut track);
+        }
+        track
+    }
 
-        Ok(Essentials { core: core, client: builder.build()?, cache: cache.clone() })
+    pub(crate) fn record(&mut self, track: &Track, submission: &Submission) {
+        if let Some(ref ignored) = submission.ignored {
+            warn!("last.fm ignored {} - {}: {}", track.artist, track.name, ignored);
+        }
+        if let Some(ref correction) = submission.correction {
+            info!("last.fm corrected {} - {}: {:?}", track.artist, track.name, correction);
+            self.map.insert(Corrections::key(track), correction.clone());
+        }
     }
 }
 
 // ----------------------------------------------------------------
 
+#[derive(Clone)]
 enum TimerMessage {
+    /// Play given track: resume it if it's current one, start new play otherwise
     Play(Track),
+    /// Start new play of given track
+    Start(Track),
+    Resume,
+    Pause,
+    Seek(Duration),
+    /// Current play is over
     Stop,
     Shutdown,
 }
 
+/// Timer message, stamped with the clock when it was sent, so the timer
+/// measures playback by player events rather than by their processing
+struct Update {
+    at: Duration,
+    unix_time: u64,
+    msg: TimerMessage,
+}
+
 enum ScrobbleMessage {
+    NowPlaying(Track),
     Scrobble(Track),
-    Shutdown,
+    /// Submit cached tracks now and report back
+    Flush(oneshot::Sender<Result<Vec<Track>>>),
+    /// Submit what's left until the deadline, persist the rest and report back
+    Shutdown(Instant, Sender<ShutdownReport>),
 }
 
 // ----------------------------------------------------------------
@@ -104,93 +392,1125 @@ enum ScrobbleMessage {
 // threading mechanics:
 // * main -play(track)-> timer
 // * main -stop()-> timer
-// * timer -scrobble(track)-> scrobbler
+// * driver -start/resume/pause/seek/stop-> timer
+// * timer -now_playing(track)-> scrobbler of every sink
+// * timer -scrobble(track)-> scrobbler of every sink
 
 // data:
-// main has: arc cache and current
-// timer has: current track, remaining count
-// scrobbler has: arc cache, essentials
+// main has: arc cache of every sink and current
+// timer has: current track, played time
+// scrobbler has: arc cache, sink, sink's runtime to block on and cache file, if any
+// timer runs on client's runtime, so its timers work with any of them
+// timer waits, played time and play timestamps all come from scrobbler's clock
 
 // ----------------------------------------------------------------
 
-pub struct Scrobbler {
-    scrobbler: Option<JoinHandle<()>>,
-    scrobble: Sender<ScrobbleMessage>,
+struct Playback {
+    play: Play,
+    position: Duration,
+    resumed: Option<Duration>,
+    threshold: Option<Duration>,
+}
 
-    timer: Option<JoinHandle<()>>,
-    update: Sender<TimerMessage>,
-    
-    cache: Cache,
+impl Playback {
+    fn new(mut track: Track, at: Duration, unix_time: u64) -> Playback {
+        track.timestamp_utc = Some(unix_time as u32);
+        Playback {
+            play: Play::new(track),
+            position: Duration::from_secs(0),
+            resumed: Some(at),
+            threshold: None,
+        }
+    }
+
+    fn played(&self, now: Duration) -> Duration {
+        self.play.played + self.resumed.map_or(Duration::from_secs(0), |r| now.checked_sub(r).unwrap_or_default())
+    }
+
+    /// Time left until the track should be scrobbled, if it's playing and not scrobbled yet
+    fn remaining(&self, now: Duration) -> Option<Duration> {
+        if self.play.scrobbled || self.resumed.is_none() {
+            return None;
+        }
+        let threshold = self.threshold?;
+        Some(threshold.checked_sub(self.played(now)).unwrap_or_default())
+    }
+
+    /// Accounts time played since last event
+    fn checkpoint(&mut self, at: Duration) {
+        if let Some(resumed) = self.resumed {
+            let played = at.checked_sub(resumed).unwrap_or_default();
+            self.play.played += played;
+            self.position += played;
+            self.resumed = Some(at);
+        }
+    }
+
+    /// Asks the policy again, since the play has changed
+    fn update(&mut self, policy: &dyn ScrobblePolicy, history: &History) {
+        self.threshold = policy.threshold(&self.play, history);
+    }
+
+    fn pause(&mut self, at: Duration) {
+        self.checkpoint(at);
+        self.resumed = None;
+    }
+
+    fn resume(&mut self, at: Duration) {
+        if self.resumed.is_none() {
+            self.resumed = Some(at);
+        }
+    }
+
+    fn seek(&mut self, at: Duration, position: Duration) {
+        self.checkpoint(at);
+        if position > self.position {
+            self.play.skipped = true;
+        }
+        self.position = position;
+    }
 }
 
-impl Scrobbler {
-    pub fn new() -> Result<Scrobbler> {
-        let (scrobble_tx, scrobble_rx) = channel();
-        let (timer_tx, timer_rx) = channel();
+/// Replaces current play, finished one goes to the history, so the policy can look back at it
+fn finish(current: &mut Option<Playback>, next: Option<Playback>, history: &mut History) {
+    if let Some(playback) = std::mem::replace(current, next) {
+        history.push(playback.play);
+    }
+}
 
-        let timer = spawn(move || {
-            let mut current: Option<Track> = None;
-            loop {
-                match timer_rx.recv() {
-                    Ok(TimerMessage::Play(track)) => {
+/// Waits for the next timer update, up to given clock deadline
+async fn recv_deadline(
+    runtime: &dyn Runtime,
+    clock: &dyn Clock,
+    updates: &mut UnboundedReceiver<Update>,
+    deadline: Option<Duration>,
+) -> StdResult<Update, RecvTimeoutError> {
+    let update = match deadline {
+        Some(deadline) => match select(updates.next(), clock.sleep_until(runtime, deadline)).await {
+            Either::Left((update, _)) => update,
+            Either::Right(_) => return Err(RecvTimeoutError::Timeout),
+        },
+        None => updates.next().await,
+    };
+    update.ok_or(RecvTimeoutError::Disconnected)
+}
+
+async fn run_timer(
+    runtime: Arc<dyn Runtime>,
+    clock: Arc<dyn Clock>,
+    policy: Arc<dyn ScrobblePolicy>,
+    mut updates: UnboundedReceiver<Update>,
+    scrobbles: Vec<Sender<ScrobbleMessage>>,
+) {
+    let mut history = History::new();
+    let mut current: Option<Playback> = None;
 
-                    },
-                    Ok(TimerMessage::Stop) => {
+    loop {
+        let now = clock.now();
+        let deadline = current.as_ref().and_then(|p| p.remaining(now)).map(|r| now + r);
+        let update = recv_deadline(&*runtime, &*clock, &mut updates, deadline).await;
 
-                    },
-                    _ => break,
+        match update {
+            Ok(Update { at, unix_time, msg: TimerMessage::Play(track) }) => {
+                let resumed = match current {
+                    Some(ref mut playback) if playback.play.track.is_same(&track) => {
+                        playback.resume(at);
+                        true
+                    }
+                    _ => false,
+                };
+                if !resumed {
+                    broadcast(&scrobbles, &track, ScrobbleMessage::NowPlaying);
+                    finish(&mut current, Some(Playback::new(track, at, unix_time)), &mut history);
                 }
             }
-        });
-
-        let scrobbler = spawn(move || {
-            loop {
-                match scrobble_rx.recv() {
-                    Ok(ScrobbleMessage::Scrobble(track)) => {},
-                    _ => break,
+            Ok(Update { at, unix_time, msg: TimerMessage::Start(track) }) => {
+                broadcast(&scrobbles, &track, ScrobbleMessage::NowPlaying);
+                finish(&mut current, Some(Playback::new(track, at, unix_time)), &mut history);
+            }
+            Ok(Update { at, msg: TimerMessage::Resume, .. }) => {
+                if let Some(ref mut playback) = current {
+                    playback.resume(at);
                 }
             }
-        });
+            Ok(Update { at, msg: TimerMessage::Pause, .. }) => {
+                if let Some(ref mut playback) = current {
+                    playback.pause(at);
+                }
+            }
+            Ok(Update { at, msg: TimerMessage::Seek(position), .. }) => {
+                // only time spent playing counts, so jumping forward doesn't bring the scrobble closer
+                if let Some(ref mut playback) = current {
+                    playback.seek(at, position);
+                }
+            }
+            Ok(Update { msg: TimerMessage::Stop, .. }) => finish(&mut current, None, &mut history),
+            Ok(Update { msg: TimerMessage::Shutdown, .. }) | Err(RecvTimeoutError::Disconnected) => break,
+            // queued updates are received first, so the deadline only passes once they're all applied
+            Err(RecvTimeoutError::Timeout) => {
+                if let Some(ref mut playback) = current {
+                    if playback.remaining(clock.now()) == Some(Duration::from_secs(0)) {
+                        playback.play.scrobbled = true;
+                        broadcast(&scrobbles, &playback.play.track, ScrobbleMessage::Scrobble);
+                    }
+                }
+                continue;
+            }
+        }
 
-        Ok(Scrobbler {
-            scrobbler: Some(scrobbler),
-            scrobble: scrobble_tx.clone(),
-            timer: Some(timer),
-            update: timer_tx.clone(),
-            cache: Arc::new(Mutex::new(VecDeque::new())),
+        if let Some(ref mut playback) = current {
+            playback.update(&*policy, &history);
+        }
+    }
+}
+
+/// Passes the track to scrobbler of every sink
+fn broadcast(scrobbles: &[Sender<ScrobbleMessage>], track: &Track, msg: fn(Track) -> ScrobbleMessage) {
+    for scrobble in scrobbles {
+        let _ = scrobble.send(msg(track.clone()));
+    }
+}
+
+// ----------------------------------------------------------------
+
+struct Submitter {
+    runtime: Arc<dyn Runtime>,
+    sink: Arc<dyn ScrobbleSink>,
+    cache: Cache,
+    cache_file: Option<PathBuf>,
+    normalizer: Option<Normalizer>,
+    corrections: Arc<Mutex<Corrections>>,
+    learn_corrections: bool,
+    dedup_window: Duration,
+    deadline: Option<Instant>,
+    next_id: u64,
+    auth_failed: Arc<AtomicBool>,
+    failures: u32,
+    retry_at: Option<Instant>,
+}
+
+impl Submitter {
+    /// Runs request on the runtime, giving up once shutdown deadline passes, if it's set
+    fn run<T, F: Future<Output = Result<T>>>(&self, request: F) -> Result<T> {
+        let deadline = match self.deadline {
+            Some(deadline) => deadline,
+            None => return runtime::block_on(&*self.runtime, request),
+        };
+        let left = deadline.saturating_duration_since(Instant::now());
+        let timeout = self.runtime.sleep(left);
+        runtime::block_on(&*self.runtime, async move {
+            match select(Box::pin(request), timeout).await {
+                Either::Left((res, _)) => res,
+                Either::Right(_) => Err(Error::io(IoErrorKind::TimedOut, "Shutdown deadline has passed")),
+            }
         })
     }
 
-    pub fn now_playing(&self, track: Option<Track>) {
-        // Some (play/resume)
-        // if played track is different from current (new play):
-        // * save the track to current (not scrobbled)
-        // * start scrobble timer
-        // if played track is the same as current (resume):
-        // * if not yet scrobbled, resume scrobble timer
-        // * if scrobbled, do nothing
+    /// Applies normalizer and learned corrections, if any, logging the changes
+    fn prepare(&self, track: Track) -> Track {
+        let track = match self.normalizer {
+            Some(ref normalizer) => {
+                let audit = normalizer.audit(&track);
+                if audit.changed() {
+                    info!("Normalized {}", audit);
+                }
+                audit.normalized
+            }
+            None => track,
+        };
+        if self.learn_corrections {
+            self.corrections.lock().unwrap().apply(track)
+        } else {
+            track
+        }
+    }
 
-        // None (stop/pause)
-        // if current track is not scrobbled, pausescrobble timer
-        // if current track is scrobbled, do nothing
+    /// Schedules next submission attempt after a temporary failure, backing off exponentially.
+    /// Session failures aren't retried on their own, they wait for re-auth.
+    fn schedule_retry(&mut self, error: Option<&Error>) {
+        let pending = !self.cache.lock().unwrap().is_empty();
+        match error {
+            Some(e) if pending && !needs_auth(e) => {
+                let delay = RETRY_MIN_DELAY_SEC
+                    .saturating_mul(1 << self.failures.min(16))
+                    .min(RETRY_MAX_DELAY_SEC);
+                self.failures += 1;
+                self.retry_at = Some(Instant::now() + Duration::from_secs(delay));
+                info!("Retrying {} submission in {}s", self.sink.name(), delay);
+            }
+            _ => {
+                self.failures = 0;
+                self.retry_at = None;
+            }
+        }
+    }
+
+    /// Returns `true` if submission is postponed after a temporary failure
+    fn backing_off(&self) -> bool {
+        self.retry_at.map_or(false, |at| at > Instant::now())
+    }
+
+    /// Saves the cache to the file, if there's one, returns whether it's saved
+    fn persist(&self) -> bool {
+        persist(&self.cache, self.cache_file.as_deref())
+    }
+
+    fn now_playing(&mut self, track: &Track) {
+        match self.run(self.sink.now_playing(track)) {
+            Ok(submission) => {
+                self.auth_failed.store(false, Ordering::SeqCst);
+                self.corrections.lock().unwrap().record(track, &submission);
+            }
+            Err(e) => {
+                warn!("Failed to update {} now playing track: {}", self.sink.name(), e);
+                if needs_auth(&e) {
+                    self.auth_failed.store(true, Ordering::SeqCst);
+                }
+            }
+        }
+    }
+
+    /// Looks up uncertain cached tracks in sink's recent tracks, drops ones it already has.
+    /// Returns `false` if they couldn't be checked, so they shouldn't be resubmitted yet.
+    fn verify(&mut self) -> bool {
+        let uncertain: Vec<Track> = {
+            let cache = self.cache.lock().unwrap();
+            cache.iter().filter(|e| e.uncertain).map(|e| e.track.clone()).collect()
+        };
+        let timestamps = uncertain.iter().filter_map(|t| t.timestamp_utc);
+        let (from, to) = match (timestamps.clone().min(), timestamps.max()) {
+            (Some(from), Some(to)) => (from as u64, to as u64),
+            _ => return true,
+        };
+        let window = self.dedup_window.as_secs();
+
+        let lookup = match self.sink.recent(from.saturating_sub(window), to + window) {
+            Some(lookup) => lookup,
+            // nothing to check against, so they are resubmitted
+            None => return true,
+        };
+        let recent = match self.run(lookup) {
+            Ok(recent) => recent,
+            Err(e) => {
+                warn!("Failed to check {} recent tracks, will retry later: {}", self.sink.name(), e);
+                return false;
+            }
+        };
+
+        let mut cache = self.cache.lock().unwrap();
+        let before = cache.len();
+        let window = self.dedup_window;
+        cache.retain(|e| !e.uncertain || !recent.iter().any(|r| is_duplicate(r, &e.track, window)));
+        for entry in cache.iter_mut() {
+            entry.uncertain = false;
+        }
+        info!("{} of {} uncertain scrobbles were already submitted", before - cache.len(), uncertain.len());
+        true
     }
 
-    // on_scrobble_timer()
-    // * push current track to scrobbler cache
+    // * check tracks with unknown submission outcome against recent tracks
     // * submit scrobble cache
     // * if succeeded, remove all ok items from the cache
     // * if failed:
-    //      * if error can be handled (like re-auth), do it
     //      * if network fail, leave the cache as is
+    //      * if it's not known whether the sink got the batch, mark it uncertain
     //      * if non-recoverable API fail, retire corresponding cache record(s)
+    //
+    // returns tracks accepted by the sink and the error that stopped submission, if any,
+    // or the first rejection, if batches were dropped
+    //
+    // cache can be changed by the user while a batch is submitted, so its entries are found by ids
+    fn submit(&mut self) -> (Vec<Track>, Option<Error>) {
+        let mut accepted = Vec::new();
+        let mut rejected = None;
+        loop {
+            let uncertain = self.cache.lock().unwrap().iter().any(|e| e.uncertain);
+            if uncertain && !self.verify() {
+                let e = Error::io(IoErrorKind::Other, "Failed to check uncertain scrobbles");
+                return (accepted, Some(e));
+            }
+
+            let (ids, batch): (Vec<u64>, Vec<Track>) = {
+                let mut cache = self.cache.lock().unwrap();
+                cache
+                    .iter_mut()
+                    .take(self.sink.max_batch())
+                    .map(|e| {
+                        e.attempts += 1;
+                        (e.id, e.track.clone())
+                    })
+                    .unzip()
+            };
+            if batch.is_empty() {
+                return (accepted, rejected);
+            }
+
+            let (retire, error) = match self.run(self.sink.scrobble(&batch)) {
+                Ok(submissions) => {
+                    let mut corrections = self.corrections.lock().unwrap();
+                    for (track, submission) in batch.iter().zip(&submissions) {
+                        corrections.record(track, submission);
+                    }
+                    accepted.extend(batch.iter().cloned());
+                    self.auth_failed.store(false, Ordering::SeqCst);
+                    (true, None)
+                }
+                // session is revoked or not there, batch isn't at fault, so it's kept until re-auth
+                Err(e) if needs_auth(&e) => {
+                    warn!("{} session is not valid, scrobbles are kept until it's renewed: {}", self.sink.name(), e);
+                    self.auth_failed.store(true, Ordering::SeqCst);
+                    let mut cache = self.cache.lock().unwrap();
+                    for entry in cache.iter_mut().filter(|p| ids.contains(&p.id)) {
+                        entry.last_error = Some(e.to_string());
+                    }
+                    (false, Some(e))
+                }
+                Err(e) if is_rejected(&e) => {
+                    warn!("{} rejected scrobble batch of {}, dropping it: {}", self.sink.name(), batch.len(), e);
+                    rejected = rejected.or(Some(e));
+                    (true, None)
+                }
+                Err(e) => {
+                    warn!("Failed to submit scrobbles to {}, will retry later: {}", self.sink.name(), e);
+                    let uncertain = outcome_unknown(&e);
+                    let mut cache = self.cache.lock().unwrap();
+                    for entry in cache.iter_mut().filter(|p| ids.contains(&p.id)) {
+                        entry.last_error = Some(e.to_string());
+                        entry.uncertain |= uncertain;
+                    }
+                    (false, Some(e))
+                }
+            };
+            if !retire {
+                return (accepted, error);
+            }
+
+            self.cache.lock().unwrap().retain(|e| !ids.contains(&e.id));
+        }
+    }
+}
+
+/// Checks whether the request could have reached the sink before it failed
+fn outcome_unknown(e: &Error) -> bool {
+    match *e {
+        Error::Io(ref e) => !matches!(
+            e.kind(),
+            IoErrorKind::ConnectionRefused
+                | IoErrorKind::NotFound
+                | IoErrorKind::PermissionDenied
+                | IoErrorKind::InvalidInput
+                | IoErrorKind::AddrNotAvailable
+        ),
+        Error::Http { status, .. } => status >= 500,
+        Error::NotJson { .. } => true,
+        _ => false,
+    }
+}
+
+/// Checks whether the error means the client has to be authenticated (again)
+fn needs_auth(e: &Error) -> bool {
+    match *e {
+        Error::Io(ref e) => e.kind() == IoErrorKind::PermissionDenied,
+        Error::Lastfm { code: Some(code), .. } => code == INVALID_SESSION_API_ERROR,
+        Error::Http { status, .. } => status == 401,
+        _ => false,
+    }
+}
+
+/// Checks whether the sink refused the batch itself, so resubmitting it won't help.
+///
+/// Other last.fm errors (like 11 "service offline", 16 "temporary error" or 29 "rate limit exceeded")
+/// are temporary, so the batch is kept and retried later.
+fn is_rejected(e: &Error) -> bool {
+    match *e {
+        Error::Lastfm { code: Some(code), .. } => REJECTED_API_ERRORS.contains(&code),
+        Error::Http { status, .. } => status == 400,
+        Error::Rejected(_) => true,
+        _ => false,
+    }
+}
+
+fn run_submitter(messages: Receiver<ScrobbleMessage>, mut submitter: Submitter) {
+    loop {
+        let msg = match submitter.retry_at {
+            Some(at) => messages.recv_timeout(at.saturating_duration_since(Instant::now())),
+            None => messages.recv().map_err(|_| RecvTimeoutError::Disconnected),
+        };
+        let msg = match msg {
+            Ok(msg) => msg,
+            Err(RecvTimeoutError::Timeout) => {
+                let (_, error) = submitter.submit();
+                submitter.schedule_retry(error.as_ref());
+                submitter.persist();
+                continue;
+            }
+            Err(RecvTimeoutError::Disconnected) => break,
+        };
+
+        match msg {
+            ScrobbleMessage::NowPlaying(track) => {
+                let track = submitter.prepare(track);
+                submitter.now_playing(&track);
+            }
+            ScrobbleMessage::Scrobble(track) => {
+                let track = submitter.prepare(track);
+                let window = submitter.dedup_window;
+                let entry = Pending::new(submitter.next_id, track);
+                if !push_unique(&mut submitter.cache.lock().unwrap(), entry, window) {
+                    continue;
+                }
+                submitter.next_id += 1;
+                submitter.persist();
+                // new scrobble is only cached while backing off, retry timer submits it
+                if !submitter.backing_off() {
+                    let (_, error) = submitter.submit();
+                    submitter.schedule_retry(error.as_ref());
+                }
+                submitter.persist();
+            }
+            ScrobbleMessage::Flush(result) => {
+                let (accepted, error) = submitter.submit();
+                submitter.schedule_retry(error.as_ref());
+                submitter.persist();
+                let _ = result.send(match error {
+                    Some(e) => Err(e),
+                    None => Ok(accepted),
+                });
+            }
+            ScrobbleMessage::Shutdown(deadline, report) => {
+                submitter.deadline = Some(deadline);
+                let (submitted, _) = submitter.submit();
+                let saved = submitter.persist();
+                let _ = report.send(ShutdownReport {
+                    submitted,
+                    pending: submitter.cache.lock().unwrap().iter().map(|e| e.track.clone()).collect(),
+                    saved_to: submitter.cache_file.clone().filter(|_| saved),
+                    complete: true,
+                    sinks: Vec::new(),
+                });
+                return;
+            }
+        }
+    }
+    submitter.persist();
+}
+
+/// Saves the cache to given file, if any, returns whether it's saved
+fn persist(cache: &Cache, path: Option<&Path>) -> bool {
+    let path = match path {
+        Some(path) => path,
+        None => return false,
+    };
+    // cache is locked while it's written, so concurrent saves don't mix
+    let cache = cache.lock().unwrap();
+    match save_cache(path, &cache) {
+        Ok(()) => true,
+        Err(e) => {
+            warn!("Failed to save scrobble cache to {}: {}", path.display(), e);
+            false
+        }
+    }
+}
+
+/// Joins the thread, unless it doesn't finish before the deadline
+fn join_until(handle: JoinHandle<()>, deadline: Instant) -> bool {
+    while !handle.is_finished() {
+        if Instant::now() >= deadline {
+            return false;
+        }
+        thread::sleep(Duration::from_millis(JOIN_POLL_MS));
+    }
+    handle.join().is_ok()
+}
+
+/// Deadline for dropped scrobbler to submit and save cached tracks (in seconds)
+pub static DEFAULT_SHUTDOWN_DEADLINE_SEC: u64 = 3;
+
+/// How often threads are checked while waiting for them to finish
+static JOIN_POLL_MS: u64 = 10;
+
+/// Outcome of `Scrobbler::shutdown()`
+#[derive(Debug, Clone, Default)]
+pub struct ShutdownReport {
+    /// Tracks submitted during shutdown
+    pub submitted: Vec<Track>,
+    /// Tracks left unsubmitted
+    pub pending: Vec<Track>,
+    /// Cache file pending tracks are saved to, `None` means they are lost
+    pub saved_to: Option<PathBuf>,
+    /// Whether scrobbler threads finished before the deadline
+    pub complete: bool,
+    /// Reports of additional sinks (see `Builder::sink()`), by sink name
+    pub sinks: Vec<(String, ShutdownReport)>,
+}
+
+// ----------------------------------------------------------------
+
+/// Scrobbler builder
+///
+/// Cache is in-memory, time is taken from `SystemClock` and tracks are scrobbled
+/// by last.fm `Rules`, unless set otherwise.
+pub struct Builder {
+    client: Client,
+    sinks: Vec<Arc<dyn ScrobbleSink>>,
+    cache_file: Option<PathBuf>,
+    clock: Option<Arc<dyn Clock>>,
+    policy: Option<Arc<dyn ScrobblePolicy>>,
+    normalizer: Option<Normalizer>,
+    learn_corrections: bool,
+    dedup_window: Duration,
+    verify_user: Option<String>,
+}
+
+impl Builder {
+    /// Constructs new scrobbler builder for given client
+    pub fn new(client: &Client) -> Builder {
+        Builder {
+            client: client.clone(),
+            sinks: Vec::new(),
+            cache_file: None,
+            clock: None,
+            policy: None,
+            normalizer: None,
+            learn_corrections: false,
+            dedup_window: Duration::from_secs(DEFAULT_DEDUP_WINDOW_SEC),
+            verify_user: None,
+        }
+    }
+
+    /// Builds and starts new scrobbler
+    pub fn build(self) -> Result<Scrobbler> {
+        let mut lastfm = Lastfm::new(&self.client);
+        if let Some(ref user) = self.verify_user {
+            lastfm = lastfm.verify_recent_tracks(user);
+        }
+        let mut sinks: Vec<Arc<dyn ScrobbleSink>> = vec![Arc::new(lastfm)];
+        sinks.extend(self.sinks.iter().cloned());
+
+        let mut submitters: Vec<Submitter> = Vec::with_capacity(sinks.len());
+        for sink in sinks {
+            if submitters.iter().any(|s| s.sink.name() == sink.name()) {
+                return Err(Error::build(format!("Duplicate scrobble sink name: {}", sink.name())));
+            }
+            // additional sinks keep their caches next to the main one
+            let cache_file = match self.cache_file {
+                Some(ref path) if !submitters.is_empty() => Some(sink_cache_file(path, sink.name())),
+                ref path => path.clone(),
+            };
+            submitters.push(self.submitter(sink, cache_file)?);
+        }
+
+        let clock = self.clock.unwrap_or_else(|| Arc::new(SystemClock::new()));
+        let policy = self.policy.unwrap_or_else(|| Arc::new(Rules::lastfm()));
+        Ok(Scrobbler::start(&self.client, submitters, clock, policy))
+    }
+
+    fn submitter(&self, sink: Arc<dyn ScrobbleSink>, cache_file: Option<PathBuf>) -> Result<Submitter> {
+        let (loaded, next_id) = match cache_file {
+            Some(ref path) => load_cache(path)?,
+            None => (VecDeque::new(), 0),
+        };
+        let mut cached = VecDeque::with_capacity(loaded.len());
+        for entry in loaded {
+            push_unique(&mut cached, entry, self.dedup_window);
+        }
+
+        Ok(Submitter {
+            runtime: sink.runtime(),
+            sink,
+            cache: Arc::new(Mutex::new(cached)),
+            cache_file,
+            normalizer: self.normalizer.clone(),
+            corrections: Arc::new(Mutex::new(Corrections::default())),
+            learn_corrections: self.learn_corrections,
+            dedup_window: self.dedup_window,
+            deadline: None,
+            next_id,
+            auth_failed: Arc::new(AtomicBool::new(false)),
+            failures: 0,
+            retry_at: None,
+        })
+    }
+
+    /// Adds a sink, that gets the same scrobbles as last.fm.
+    ///
+    /// Every sink has its own queue and retry state, check `Scrobbler::queue()`.
+    /// If there's a cache file, sink's cache is kept next to it, as `<cache file>.<sink name>`.
+    pub fn sink<S: ScrobbleSink + 'static>(mut self, sink: S) -> Builder {
+        self.sinks.push(Arc::new(sink));
+        self
+    }
+
+    /// Sets persistent cache file.
+    ///
+    /// Tracks cached in given file are loaded and retried with next scrobble,
+    /// file is updated every time cache changes and when scrobbler is dropped.
+    pub fn cache_file<P: Into<PathBuf>>(mut self, path: P) -> Builder {
+        self.cache_file = Some(path.into());
+        self
+    }
+
+    /// Sets clock, that measures playback and stamps scrobbles
+    pub fn clock<C: Clock + 'static>(mut self, clock: C) -> Builder {
+        self.clock = Some(Arc::new(clock));
+        self
+    }
+
+    /// Sets policy, that decides when tracks are scrobbled
+    pub fn policy<P: ScrobblePolicy + 'static>(mut self, policy: P) -> Builder {
+        self.policy = Some(Arc::new(policy));
+        self
+    }
+
+    /// Sets normalizer, that cleans up track tags before they're submitted
+    pub fn normalizer(mut self, normalizer: Normalizer) -> Builder {
+        self.normalizer = Some(normalizer);
+        self
+    }
+
+    /// Enables applying corrections last.fm made to earlier submissions of the same
+    /// tracks before they're submitted (after normalizer). Corrections are always
+    /// recorded, see `Scrobbler::corrections()`.
+    pub fn learn_corrections(mut self, learn: bool) -> Builder {
+        self.learn_corrections = learn;
+        self
+    }
+
+    /// Sets how far apart (by timestamp) scrobbles of the same track have to be
+    /// to be cached separately, closer ones are dropped as duplicates
+    pub fn dedup_window(mut self, window: Duration) -> Builder {
+        self.dedup_window = window;
+        self
+    }
+
+    /// Enables checking given user's recent tracks before resubmitting tracks,
+    /// that failed to be submitted without knowing whether last.fm got them.
+    /// Tracks found there are dropped from the cache instead.
+    pub fn verify_recent_tracks(mut self, user: &str) -> Builder {
+        self.verify_user = Some(user.to_owned());
+        self
+    }
+}
+
+/// Cache file of an additional sink: `<cache file>.<sink name>`
+pub(crate) fn sink_cache_file(path: &Path, name: &str) -> PathBuf {
+    let mut file = path.as_os_str().to_owned();
+    file.push(".");
+    file.push(name);
+    PathBuf::from(file)
+}
+
+// ----------------------------------------------------------------
+
+/// Submission queue of a single sink
+///
+/// Queue has its own cache, submission thread and failure state,
+/// so sinks are retried independently of each other.
+pub struct Queue {
+    name: String,
+    thread: Option<JoinHandle<()>>,
+    scrobble: Sender<ScrobbleMessage>,
+    cache: Cache,
+    cache_file: Option<PathBuf>,
+    corrections: Arc<Mutex<Corrections>>,
+    auth_failed: Arc<AtomicBool>,
+}
+
+impl Queue {
+    fn start(submitter: Submitter) -> Queue {
+        let (scrobble_tx, scrobble_rx) = channel();
+        Queue {
+            name: submitter.sink.name().to_owned(),
+            cache: submitter.cache.clone(),
+            cache_file: submitter.cache_file.clone(),
+            corrections: submitter.corrections.clone(),
+            auth_failed: submitter.auth_failed.clone(),
+            scrobble: scrobble_tx,
+            thread: Some(spawn(move || run_submitter(scrobble_rx, submitter))),
+        }
+    }
+
+    /// Returns name of the sink
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// Returns file the cache is saved to, if there's one
+    pub fn cache_file(&self) -> Option<&Path> {
+        self.cache_file.as_deref()
+    }
+
+    /// Returns number of tracks waiting to be submitted
+    pub fn pending_count(&self) -> usize {
+        self.cache.lock().unwrap().len()
+    }
+
+    /// Returns snapshot of cached tracks, waiting to be submitted
+    pub fn pending(&self) -> Vec<Pending> {
+        self.cache.lock().unwrap().iter().cloned().collect()
+    }
+
+    /// Submits cached tracks right away.
+    ///
+    /// Resolves into tracks accepted by the sink, or the error that stopped submission
+    /// (tracks that failed stay cached). If the sink rejected a batch, it's dropped from the cache,
+    /// the rest is still submitted and the rejection is reported. Future doesn't depend on any runtime.
+    pub fn flush(&self) -> impl Future<Output = Result<Vec<Track>>> {
+        let (tx, rx) = oneshot::channel();
+        let sent = self.scrobble.send(ScrobbleMessage::Flush(tx)).is_ok();
+        async move {
+            let gone = || Error::io(IoErrorKind::BrokenPipe, "Scrobbler has stopped");
+            if !sent {
+                return Err(gone());
+            }
+            rx.await.map_err(|_| gone())?
+        }
+    }
+
+    /// Removes cached track with given id, returns it if it was there
+    pub fn remove(&self, id: u64) -> Option<Pending> {
+        let removed = {
+            let mut cache = self.cache.lock().unwrap();
+            let index = cache.iter().position(|e| e.id == id)?;
+            cache.remove(index)
+        };
+        persist(&self.cache, self.cache_file.as_deref());
+        removed
+    }
+
+    /// Removes all cached tracks, returns how many were removed
+    pub fn clear(&self) -> usize {
+        let removed = {
+            let mut cache = self.cache.lock().unwrap();
+            let removed = cache.len();
+            cache.clear();
+            removed
+        };
+        persist(&self.cache, self.cache_file.as_deref());
+        removed
+    }
+
+    /// Returns `true` if last submission failed because the session (or token) is missing or revoked.
+    ///
+    /// Scrobbles are kept meanwhile, but the queue doesn't retry them on its own:
+    /// the caller has to authenticate the session again and call `flush()`.
+    /// last.fm sink shares the session with its client, so it's enough to authenticate the client.
+    pub fn needs_auth(&self) -> bool {
+        self.auth_failed.load(Ordering::SeqCst)
+    }
+
+    /// Returns snapshot of corrections the sink made to submitted tracks so far
+    pub fn corrections(&self) -> Corrections {
+        self.corrections.lock().unwrap().clone()
+    }
+
+    fn pending_tracks(&self) -> Vec<Track> {
+        self.cache.lock().unwrap().iter().map(|e| e.track.clone()).collect()
+    }
+
+    /// Asks submission thread to finish, the report is sent back through returned channel
+    fn request_shutdown(&self, deadline: Instant) -> Receiver<ShutdownReport> {
+        let (report_tx, report_rx) = channel();
+        if self.scrobble.send(ScrobbleMessage::Shutdown(deadline, report_tx)).is_err() {
+            warn!("{} submission thread has already stopped", self.name);
+        }
+        report_rx
+    }
+
+    fn finish(&mut self, report: Receiver<ShutdownReport>, deadline: Instant) -> ShutdownReport {
+        let left = deadline.saturating_duration_since(Instant::now());
+        let report = report.recv_timeout(left);
+        let mut complete = true;
+        if let Some(thread) = self.thread.take() {
+            complete &= join_until(thread, deadline);
+        }
+
+        match report {
+            Ok(mut report) => {
+                report.complete &= complete;
+                report
+            }
+            // submission thread is stuck, save the cache from here
+            Err(_) => {
+                let saved = persist(&self.cache, self.cache_file.as_deref());
+                ShutdownReport {
+                    submitted: Vec::new(),
+                    pending: self.pending_tracks(),
+                    saved_to: self.cache_file.clone().filter(|_| saved),
+                    complete: false,
+                    sinks: Vec::new(),
+                }
+            }
+        }
+    }
+}
+
+// ----------------------------------------------------------------
+
+/// Ready-to-use scrobbler
+///
+/// Follows playback state reported through `now_playing()`, updates now playing track
+/// and scrobbles tracks played for half their duration (or 4 minutes),
+/// or by other `ScrobblePolicy` set with `Builder::policy()`.
+/// Tracks that failed to be submitted are kept in cache and retried with next scrobble.
+///
+/// Client has to be authenticated, scrobbler shares the session with it.
+/// Tracks are submitted to last.fm through the client and to additional sinks,
+/// if there are any (see `Builder::sink()`). Queue methods of the scrobbler itself,
+/// like `pending()` or `flush()`, refer to last.fm queue, use `queue()` for the others.
+///
+/// Cache can be backed by a file (see `with_cache_file()`) to survive restarts.
+/// Timer and submission threads block on client's (and sinks') runtime, so scrobbler
+/// works with any `Runtime` implementation.
+pub struct Scrobbler {
+    queues: Vec<Queue>,
+
+    timer: Option<JoinHandle<()>>,
+    driver: Driver,
+    clock: Arc<dyn Clock>,
+}
+
+impl Scrobbler {
+    pub fn new(client: &Client) -> Result<Scrobbler> {
+        Scrobbler::builder(client).build()
+    }
+
+    /// Constructs scrobbler with persistent cache, see `Builder::cache_file()`
+    pub fn with_cache_file<P: Into<PathBuf>>(client: &Client, path: P) -> Result<Scrobbler> {
+        Scrobbler::builder(client).cache_file(path).build()
+    }
+
+    /// Constructs scrobbler builder for given client
+    pub fn builder(client: &Client) -> Builder {
+        Builder::new(client)
+    }
+
+    fn start(client: &Client, submitters: Vec<Submitter>, clock: Arc<dyn Clock>, policy: Arc<dyn ScrobblePolicy>) -> Scrobbler {
+        let (timer_tx, timer_rx) = unbounded();
+        let queues: Vec<Queue> = submitters.into_iter().map(Queue::start).collect();
+
+        let timer_runtime = client.runtime();
+        let timer_clock = clock.clone();
+        let timer_scrobbles = queues.iter().map(|q| q.scrobble.clone()).collect();
+        let timer = spawn(move || {
+            let runtime = timer_runtime.clone();
+            runtime::block_on(&*runtime, run_timer(timer_runtime, timer_clock, policy, timer_rx, timer_scrobbles))
+        });
+
+        Scrobbler {
+            queues,
+            timer: Some(timer),
+            driver: Driver { targets: vec![Target { update: timer_tx, clock: clock.clone() }] },
+            clock,
+        }
+    }
+
+    // Some (play/resume)
+    // if played track is different from current (new play):
+    // * save the track to current (not scrobbled)
+    // * start scrobble timer
+    // if played track is the same as current (resume):
+    // * if not yet scrobbled, resume scrobble timer
+    // * if scrobbled, do nothing
+
+    // None (stop/pause)
+    // if current track is not scrobbled, pause scrobble timer
+    // if current track is scrobbled, do nothing
+    pub fn now_playing(&self, track: Option<Track>) {
+        let msg = match track {
+            Some(track) => TimerMessage::Play(track),
+            None => TimerMessage::Pause,
+        };
+        self.driver.send(msg);
+    }
+
+    /// Scrobbles a finished play right away, like one played offline.
+    ///
+    /// Play start time should be set with `Track::timestamp()`, current time is used otherwise.
+    pub fn scrobble(&self, mut track: Track) {
+        if track.timestamp_utc.is_none() {
+            track.timestamp_utc = Some(self.clock.unix_time() as u32);
+        }
+        for queue in &self.queues {
+            let _ = queue.scrobble.send(ScrobbleMessage::Scrobble(track.clone()));
+        }
+    }
+
+    /// Returns driver, that feeds player events to this scrobbler
+    pub fn driver(&self) -> Driver {
+        self.driver.clone()
+    }
+
+    /// Follows given player source on a separate thread.
+    ///
+    /// Thread stops once the source is closed or the scrobbler is dropped
+    /// (though the latter is only noticed on next event).
+    pub fn follow<S>(&self, mut source: S) -> JoinHandle<()>
+    where
+        S: PlayerSource + Send + 'static,
+    {
+        let driver = self.driver();
+        spawn(move || driver.run(&mut source))
+    }
+
+    /// Returns queue of the sink with given name, last.fm one is called `lastfm`
+    pub fn queue(&self, name: &str) -> Option<&Queue> {
+        self.queues.iter().find(|q| q.name == name)
+    }
+
+    /// Returns queues of all sinks, last.fm one goes first
+    pub fn queues(&self) -> &[Queue] {
+        &self.queues
+    }
+
+    fn primary(&self) -> &Queue {
+        &self.queues[0]
+    }
+
+    /// Returns number of tracks waiting to be submitted to last.fm
+    pub fn pending_count(&self) -> usize {
+        self.primary().pending_count()
+    }
+
+    /// Stops the scrobbler: makes last attempt to submit cached tracks and saves
+    /// what's left to the cache file, giving up once the deadline passes.
+    ///
+    /// Returned report is the one of last.fm, reports of additional sinks are in its `sinks`.
+    /// Dropped scrobbler does the same with a short default deadline.
+    pub fn shutdown(mut self, deadline: Duration) -> ShutdownReport {
+        self.stop(deadline)
+    }
+
+    fn stop(&mut self, deadline: Duration) -> ShutdownReport {
+        let deadline = Instant::now() + deadline;
+        let mut complete = true;
+
+        // current play is not over, so it's not scrobbled
+        if !self.driver.send(TimerMessage::Shutdown) {
+            warn!("Scrobbler timer has already stopped");
+        }
+        if let Some(timer) = self.timer.take() {
+            complete &= join_until(timer, deadline);
+        }
+
+        // sinks share the deadline, so they all get to submit at once
+        let requested: Vec<_> = self.queues.iter().map(|q| q.request_shutdown(deadline)).collect();
+        let mut reports = self
+            .queues
+            .iter_mut()
+            .zip(requested)
+            .map(|(queue, report)| (queue.name.clone(), queue.finish(report, deadline)));
+
+        let (_, mut report) = reports.next().expect("Scrobbler has no last.fm queue");
+        report.complete &= complete;
+        report.sinks = reports.collect();
+        report
+    }
+
+    /// Returns snapshot of cached tracks, waiting to be submitted to last.fm
+    pub fn pending(&self) -> Vec<Pending> {
+        self.primary().pending()
+    }
+
+    /// Submits tracks cached for last.fm right away, see `Queue::flush()`
+    pub fn flush(&self) -> impl Future<Output = Result<Vec<Track>>> {
+        self.primary().flush()
+    }
+
+    /// Removes track with given id from last.fm cache, returns it if it was there
+    pub fn remove(&self, id: u64) -> Option<Pending> {
+        self.primary().remove(id)
+    }
+
+    /// Removes all tracks from last.fm cache, returns how many were removed
+    pub fn clear(&self) -> usize {
+        self.primary().clear()
+    }
+
+    /// Returns `true` if last submission failed because the session is missing or revoked.
+    ///
+    /// Scrobbles are kept meanwhile, but scrobbler doesn't retry them on its own:
+    /// the caller has to authenticate the client again (scrobbler shares its session)
+    /// and call `flush()`.
+    pub fn needs_auth(&self) -> bool {
+        self.primary().needs_auth()
+    }
+
+    /// Returns snapshot of corrections last.fm made to submitted tracks so far
+    pub fn corrections(&self) -> Corrections {
+        self.primary().corrections()
+    }
 }
 
 impl Drop for Scrobbler {
     fn drop(&mut self) {
-        self.scrobble.send(ScrobbleMessage::Shutdown);
-        self.update.send(TimerMessage::Shutdown);
+        if self.queues.iter().all(|q| q.thread.is_none()) {
+            return;
+        }
+        let report = self.stop(Duration::from_secs(DEFAULT_SHUTDOWN_DEADLINE_SEC));
+        let pending = report.pending.len() + report.sinks.iter().map(|(_, r)| r.pending.len()).sum::<usize>();
+        if pending != 0 {
+            info!("Scrobbler is dropped with {} pending scrobbles", pending);
+        }
+    }
+}
+
+// ----------------------------------------------------------------
+
+/// Translates player events into scrobbler updates
+///
+/// Unlike `Scrobbler::now_playing()`, events tell repeats from resumes and seeks from
+/// playback, so nothing is scrobbled twice or earlier than it should be.
+/// Driver is cheap to clone and can be moved to the thread that pulls a player source.
+/// Drivers of several scrobblers can be merged to follow a single source.
+#[derive(Clone)]
+pub struct Driver {
+    targets: Vec<Target>,
+}
 
-        self.scrobbler.take().and_then(|h| h.join().ok());
-        self.timer.take().and_then(|h| h.join().ok());
+#[derive(Clone)]
+struct Target {
+    update: UnboundedSender<Update>,
+    clock: Arc<dyn Clock>,
+}
+
+impl Driver {
+    /// Combines drivers, so every event is passed to all their scrobblers
+    pub fn merge<I: IntoIterator<Item = Driver>>(drivers: I) -> Driver {
+        Driver { targets: drivers.into_iter().flat_map(|d| d.targets).collect() }
+    }
+
+    /// Passes single player event to the scrobbler(s), returns `false` if they are all gone
+    pub fn handle(&self, event: PlayerEvent) -> bool {
+        let msg = match event {
+            PlayerEvent::TrackChanged(track) => TimerMessage::Start(track),
+            PlayerEvent::Play => TimerMessage::Resume,
+            PlayerEvent::Pause => TimerMessage::Pause,
+            PlayerEvent::Seek(position) => TimerMessage::Seek(position),
+            PlayerEvent::Stop => TimerMessage::Stop,
+        };
+        self.send(msg)
+    }
+
+    /// Pulls events from the source until it's closed or the scrobbler(s) are gone
+    pub fn run<S: PlayerSource + ?Sized>(&self, source: &mut S) {
+        while let Some(event) = source.next_event() {
+            if !self.handle(event) {
+                return;
+            }
+        }
+    }
+
+    fn send(&self, msg: TimerMessage) -> bool {
+        let mut alive = false;
+        for target in &self.targets {
+            let update = Update {
+                at: target.clock.now(),
+                unix_time: target.clock.unix_time(),
+                msg: msg.clone(),
+            };
+            alive |= target.update.unbounded_send(update).is_ok();
+        }
+        alive
     }
 }
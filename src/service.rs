@@ -0,0 +1,283 @@
+use std::io::ErrorKind as IoErrorKind;
+use std::str::FromStr;
+use std::sync::Mutex;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use url::Url;
+use url::form_urlencoded::Serializer;
+
+use super::{LASTFM_API_BASE_URL, LASTFM_API_AUTH_URL};
+use crate::client::Client;
+use crate::scrobbler::Track;
+use crate::utils::{Error, Result};
+
+// ----------------------------------------------------------------
+
+/// Base URL for Libre.fm API methods
+pub static LIBREFM_API_BASE_URL: &str = "https://libre.fm/2.0/";
+
+/// Base URL for Libre.fm desktop authentication
+pub static LIBREFM_API_AUTH_URL: &str = "https://libre.fm/api/auth/";
+
+/// Libre.fm Audioscrobbler 1.2 handshake URL
+pub static LIBREFM_HANDSHAKE_URL: &str = "https://turtle.libre.fm/";
+
+/// Audioscrobbler protocol version, that is sent in the handshake
+pub static AUDIOSCROBBLER_PROTOCOL_VERSION: &str = "1.2.1";
+
+/// Client identifier, that is sent in the handshake.
+/// It's the one reserved for testing, servers accept it, but may ignore it in stats.
+pub static AUDIOSCROBBLER_CLIENT_ID: &str = "tst";
+
+// ----------------------------------------------------------------
+
+/// Scrobbling service profile, check `Builder::service()`.
+///
+/// Profile sets API and auth endpoints of the service and the protocol tracks are submitted with.
+/// Read methods and authentication always use last.fm-compatible 2.0 API of the service.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Service {
+    /// last.fm, the default
+    Lastfm,
+    /// Libre.fm through its last.fm-compatible 2.0 API
+    LibreFm,
+    /// Libre.fm, tracks are submitted with legacy Audioscrobbler 1.2 protocol
+    LibreFmLegacy,
+    /// Self-hosted GNU FM server with given root url, like `https://fm.example.org/`
+    GnuFm(String),
+    /// Any server, that accepts Audioscrobbler 1.2 submissions, with given handshake url.
+    /// API and auth urls are left as they are.
+    Audioscrobbler(String),
+}
+
+impl Service {
+    /// Returns base API url of the service, if it's known
+    pub fn base_url(&self) -> Option<String> {
+        match *self {
+            Service::Lastfm => Some(LASTFM_API_BASE_URL.to_owned()),
+            Service::LibreFm | Service::LibreFmLegacy => Some(LIBREFM_API_BASE_URL.to_owned()),
+            Service::GnuFm(ref root) => Some(format!("{}/2.0/", root.trim_end_matches('/'))),
+            Service::Audioscrobbler(_) => None,
+        }
+    }
+
+    /// Returns desktop auth url of the service, if it's known
+    pub fn auth_url(&self) -> Option<String> {
+        match *self {
+            Service::Lastfm => Some(LASTFM_API_AUTH_URL.to_owned()),
+            Service::LibreFm | Service::LibreFmLegacy => Some(LIBREFM_API_AUTH_URL.to_owned()),
+            Service::GnuFm(ref root) => Some(format!("{}/api/auth/", root.trim_end_matches('/'))),
+            Service::Audioscrobbler(_) => None,
+        }
+    }
+
+    /// Returns Audioscrobbler 1.2 handshake url, if tracks are submitted with legacy protocol
+    pub fn handshake_url(&self) -> Option<String> {
+        match *self {
+            Service::LibreFmLegacy => Some(LIBREFM_HANDSHAKE_URL.to_owned()),
+            Service::Audioscrobbler(ref url) => Some(url.clone()),
+            _ => None,
+        }
+    }
+}
+
+/// Parses `lastfm`, `librefm`, `librefm-legacy`, `gnufm:<root url>` or `audioscrobbler:<handshake url>`
+impl FromStr for Service {
+    type Err = Error;
+
+    fn from_str(name: &str) -> Result<Service> {
+        let (kind, url) = match name.find(':') {
+            Some(colon) => (&name[..colon], Some(name[colon + 1..].trim().to_owned())),
+            None => (name, None),
+        };
+        match (kind.trim(), url) {
+            ("lastfm", None) => Ok(Service::Lastfm),
+            ("librefm", None) => Ok(Service::LibreFm),
+            ("librefm-legacy", None) => Ok(Service::LibreFmLegacy),
+            ("gnufm", Some(url)) => Ok(Service::GnuFm(url)),
+            ("audioscrobbler", Some(url)) => Ok(Service::Audioscrobbler(url)),
+            _ => Err(Error::io(IoErrorKind::InvalidInput, format!("Unknown service {}", name))),
+        }
+    }
+}
+
+impl Default for Service {
+    fn default() -> Service {
+        Service::Lastfm
+    }
+}
+
+// ----------------------------------------------------------------
+
+/// Submission session, obtained in the handshake
+#[derive(Debug, Clone)]
+struct Handshake {
+    id: String,
+    now_playing: Url,
+    submission: Url,
+}
+
+/// Legacy Audioscrobbler 1.2 submission state, shared between client clones
+///
+/// Check https://www.last.fm/api/submissions for protocol details.
+pub(crate) struct Legacy {
+    handshake_url: Url,
+    user: String,
+    handshake: Mutex<Option<Handshake>>,
+}
+
+impl Legacy {
+    pub(crate) fn new(handshake_url: Url, user: String) -> Legacy {
+        Legacy {
+            handshake_url,
+            user,
+            handshake: Mutex::new(None),
+        }
+    }
+}
+
+fn unix_time() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or(0)
+}
+
+fn status(body: &str) -> &str {
+    body.lines().next().unwrap_or("").trim()
+}
+
+/// Turns failed handshake or submission status into an error
+///
+/// `BADTIME` and failures about timestamps mean the submission itself is bad, so they're
+/// reported as `Error::Rejected` and the scrobbler drops the batch instead of resubmitting it.
+fn rejected(status: &str) -> Error {
+    let message = format!("Audioscrobbler server responded with {}", status);
+    let kind = match status.split_whitespace().next().unwrap_or("") {
+        "BADAUTH" | "BADSESSION" => IoErrorKind::PermissionDenied,
+        "BADTIME" => return Error::rejected(message),
+        "FAILED" if status.to_lowercase().contains("timestamp") => return Error::rejected(message),
+        _ => IoErrorKind::Other,
+    };
+    Error::io(kind, message)
+}
+
+fn parse_handshake(body: &str) -> Result<Handshake> {
+    let mut lines = body.lines().map(str::trim);
+    match lines.next() {
+        Some("OK") => (),
+        other => return Err(rejected(other.unwrap_or(""))),
+    }
+
+    let malformed = || Error::io(IoErrorKind::InvalidData, "Malformed Audioscrobbler handshake response");
+    let id = lines.next().filter(|id| !id.is_empty()).ok_or_else(malformed)?.to_owned();
+    let mut url = || -> Result<Url> { lines.next().ok_or_else(malformed)?.parse().map_err(|_| malformed()) };
+    Ok(Handshake {
+        id,
+        now_playing: url()?,
+        submission: url()?,
+    })
+}
+
+pub(crate) fn now_playing_form(track: &Track) -> Vec<(String, String)> {
+    vec![
+        ("a".to_owned(), track.artist.clone()),
+        ("t".to_owned(), track.name.clone()),
+        ("b".to_owned(), track.album.clone().unwrap_or_default()),
+        ("l".to_owned(), track.duration_sec.to_string()),
+        ("n".to_owned(), track.track_number.map(|n| n.to_string()).unwrap_or_default()),
+        ("m".to_owned(), track.mbid.clone().unwrap_or_default()),
+    ]
+}
+
+pub(crate) fn submission_form(tracks: &[Track]) -> Result<Vec<(String, String)>> {
+    let mut form = Vec::new();
+    for (i, track) in tracks.iter().enumerate() {
+        let timestamp = track.timestamp_utc.ok_or_else(|| {
+            Error::io(IoErrorKind::InvalidInput, "no scrobble timestamp set")
+        })?;
+        // "E" is personalised recommendation, chosen by the player rather than the user
+        let source = if track.chosen_by_user == Some(false) { "E" } else { "P" };
+
+        form.push((format!("a[{}]", i), track.artist.clone()));
+        form.push((format!("t[{}]", i), track.name.clone()));
+        form.push((format!("i[{}]", i), timestamp.to_string()));
+        form.push((format!("o[{}]", i), source.to_owned()));
+        form.push((format!("r[{}]", i), String::new()));
+        form.push((format!("l[{}]", i), track.duration_sec.to_string()));
+        form.push((format!("b[{}]", i), track.album.clone().unwrap_or_default()));
+        form.push((format!("n[{}]", i), track.track_number.map(|n| n.to_string()).unwrap_or_default()));
+        form.push((format!("m[{}]", i), track.mbid.clone().unwrap_or_default()));
+    }
+    Ok(form)
+}
+
+/// Legacy Audioscrobbler 1.2 submissions
+impl Client {
+    /// Submits now playing notification through legacy protocol
+    pub(crate) async fn legacy_now_playing(&self, legacy: &Legacy, track: &Track) -> Result<()> {
+        self.legacy_submit(legacy, false, now_playing_form(track)).await
+    }
+
+    /// Submits batch of tracks through legacy protocol
+    pub(crate) async fn legacy_scrobble(&self, legacy: &Legacy, tracks: &[Track]) -> Result<()> {
+        self.legacy_submit(legacy, true, submission_form(tracks)?).await
+    }
+
+    async fn legacy_submit(&self, legacy: &Legacy, scrobble: bool, form: Vec<(String, String)>) -> Result<()> {
+        // session may expire at any time, new handshake is made once when it does
+        for _ in 0..2 {
+            let cached = legacy.handshake.lock().unwrap().clone();
+            let handshake = match cached {
+                Some(handshake) => handshake,
+                None => {
+                    let handshake = self.legacy_handshake(legacy).await?;
+                    *legacy.handshake.lock().unwrap() = Some(handshake.clone());
+                    handshake
+                }
+            };
+
+            let body = Serializer::new(String::new())
+                .append_pair("s", &handshake.id)
+                .extend_pairs(form.iter())
+                .finish();
+            let url = if scrobble { &handshake.submission } else { &handshake.now_playing };
+            let reply = self.send_text(url, Some(&body)).await?;
+
+            match status(&reply) {
+                "OK" => return Ok(()),
+                "BADSESSION" => *legacy.handshake.lock().unwrap() = None,
+                other => return Err(rejected(other)),
+            }
+        }
+        Err(rejected("BADSESSION"))
+    }
+
+    /// Makes handshake with web service authentication: session key and a token
+    /// derived from the shared secret
+    async fn legacy_handshake(&self, legacy: &Legacy) -> Result<Handshake> {
+        let missing = |what: &str| {
+            Error::io(IoErrorKind::PermissionDenied, format!("Can't make Audioscrobbler handshake: no {} set", what))
+        };
+        let session = self.session().ok_or_else(|| missing("session key"))?;
+        let secret = self.secret().ok_or_else(|| missing("shared secret"))?;
+
+        let timestamp = unix_time().to_string();
+        let token = format!("{:x}", md5::compute(format!("{}{}", secret, timestamp)));
+
+        let mut url = legacy.handshake_url.clone();
+        url.query_pairs_mut()
+            .append_pair("hs", "true")
+            .append_pair("p", AUDIOSCROBBLER_PROTOCOL_VERSION)
+            .append_pair("c", AUDIOSCROBBLER_CLIENT_ID)
+            .append_pair("v", env!("CARGO_PKG_VERSION"))
+            .append_pair("u", &legacy.user)
+            .append_pair("t", &timestamp)
+            .append_pair("a", &token)
+            .append_pair("api_key", self.api_key())
+            .append_pair("sk", &session);
+
+        let reply = self.send_text(&url, None).await?;
+        parse_handshake(&reply)
+    }
+}
@@ -0,0 +1,84 @@
+use std::env;
+use std::fs::{self, OpenOptions};
+use std::io::{ErrorKind as IoErrorKind, Write};
+use std::path::{Path, PathBuf};
+
+use crate::utils::{Error, Result};
+
+// ----------------------------------------------------------------
+
+/// Authenticated last.fm session, that can be stored between runs.
+///
+/// last.fm session keys don't expire, so once the user went through one of auth flows,
+/// the key can be saved and passed to `Builder::session_key()` on next start.
+///
+/// Session is stored as a small `name=value` text file, readable only by its owner (on Unix).
+#[derive(Debug, Clone, PartialEq)]
+pub struct Session {
+    /// Session key
+    pub key: String,
+    /// Name of the user session belongs to, if known
+    pub user: Option<String>,
+}
+
+impl Session {
+    /// Constructs new session
+    pub fn new(key: &str, user: Option<&str>) -> Session {
+        Session {
+            key: key.to_owned(),
+            user: user.map(|u| u.to_owned()),
+        }
+    }
+
+    /// Default session file location: `$XDG_CONFIG_HOME/first-fm/session` or `~/.config/first-fm/session`
+    pub fn default_path() -> Option<PathBuf> {
+        let config = env::var_os("XDG_CONFIG_HOME")
+            .map(PathBuf::from)
+            .or_else(|| env::var_os("HOME").map(|home| Path::new(&home).join(".config")))?;
+        Some(config.join("first-fm").join("session"))
+    }
+
+    /// Loads session from given file, returns `None` if there's no such file
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<Option<Session>> {
+        let contents = match fs::read_to_string(path) {
+            Ok(contents) => contents,
+            Err(ref e) if e.kind() == IoErrorKind::NotFound => return Ok(None),
+            Err(e) => return Err(e.into()),
+        };
+
+        let (mut key, mut user) = (None, None);
+        for line in contents.lines() {
+            match line.find('=').map(|pos| (line[..pos].trim(), line[pos + 1..].trim())) {
+                Some(("key", value)) => key = Some(value.to_owned()),
+                Some(("user", value)) => user = Some(value.to_owned()),
+                _ => {}
+            }
+        }
+
+        let key = key.ok_or_else(|| Error::io(IoErrorKind::InvalidData, "No session key in session file"))?;
+        Ok(Some(Session { key, user }))
+    }
+
+    /// Saves session to given file, creating parent directories if needed
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> Result<()> {
+        let path = path.as_ref();
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir)?;
+        }
+
+        let mut options = OpenOptions::new();
+        options.write(true).create(true).truncate(true);
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::OpenOptionsExt;
+            options.mode(0o600);
+        }
+
+        let mut file = options.open(path)?;
+        writeln!(file, "key={}", self.key)?;
+        if let Some(ref user) = self.user {
+            writeln!(file, "user={}", user)?;
+        }
+        Ok(())
+    }
+}
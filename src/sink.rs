@@ -0,0 +1,283 @@
+use std::io::ErrorKind as IoErrorKind;
+use std::sync::Arc;
+
+use url::{ParseError, Url};
+use serde_json::{json, Value};
+
+use crate::client::Client;
+use crate::http;
+use crate::runtime::{self, Runtime};
+use crate::scrobbler::Track;
+use crate::utils::{Data, Error, Result};
+use crate::write::{Submission, MAX_SCROBBLE_BATCH};
+
+// ----------------------------------------------------------------
+
+/// Base URL for ListenBrainz API
+pub static LISTENBRAINZ_API_URL: &str = "https://api.listenbrainz.org/";
+
+/// Max number of listens submitted at once.
+/// ListenBrainz takes up to 1000, smaller batches are cheaper to retry.
+pub static LISTENBRAINZ_MAX_BATCH: usize = 100;
+
+/// Max number of recent tracks fetched to check uncertain scrobbles
+static RECENT_TRACKS_LIMIT: usize = 200;
+
+// ----------------------------------------------------------------
+
+/// Service, that scrobbler submits plays to.
+///
+/// Every sink of a scrobbler gets its own queue, cache and retry state (see `scrobbler::Queue`),
+/// so a service that is down or rejects the session doesn't hold back the others.
+pub trait ScrobbleSink: Send + Sync {
+    /// Short name, that tells sinks apart in logs and queue lookups
+    fn name(&self) -> &str;
+
+    /// Max number of tracks submitted in a single request
+    fn max_batch(&self) -> usize;
+
+    /// Runtime, that sink requests are run on
+    fn runtime(&self) -> Arc<dyn Runtime>;
+
+    /// Updates now playing track
+    fn now_playing<'a>(&'a self, track: &'a Track) -> Data<'a, Submission>;
+
+    /// Submits batch of tracks, resolves into the result of each of them, in the same order
+    fn scrobble<'a>(&'a self, tracks: &'a [Track]) -> Data<'a, Vec<Submission>>;
+
+    /// Fetches tracks the service has recorded between given unix times.
+    ///
+    /// It's used to check scrobbles, that were cut off by a network failure, before they
+    /// are resubmitted. `None` means lookups aren't supported and they are resubmitted as is.
+    fn recent(&self, _from: u64, _to: u64) -> Option<Data<'_, Vec<Track>>> {
+        None
+    }
+}
+
+// ----------------------------------------------------------------
+
+/// last.fm sink, submits tracks through given client
+///
+/// Submissions go wherever the client is set up to send them, so it works with
+/// compatible services as well (see `Builder::service()`).
+#[derive(Clone)]
+pub struct Lastfm {
+    name: String,
+    client: Client,
+    user: Option<String>,
+}
+
+impl Lastfm {
+    /// Constructs sink for given client, it has to be authenticated
+    pub fn new(client: &Client) -> Lastfm {
+        Lastfm {
+            name: "lastfm".to_owned(),
+            client: client.clone(),
+            user: None,
+        }
+    }
+
+    /// Updates sink name, `lastfm` by default.
+    /// Sinks of a scrobbler need different names, so it's set for other last.fm-compatible services.
+    pub fn name(mut self, name: &str) -> Lastfm {
+        self.name = name.to_owned();
+        self
+    }
+
+    /// Enables recent tracks lookups of given user, see `ScrobbleSink::recent()`
+    pub fn verify_recent_tracks(mut self, user: &str) -> Lastfm {
+        self.user = Some(user.to_owned());
+        self
+    }
+}
+
+impl ScrobbleSink for Lastfm {
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    fn max_batch(&self) -> usize {
+        MAX_SCROBBLE_BATCH
+    }
+
+    fn runtime(&self) -> Arc<dyn Runtime> {
+        self.client.runtime()
+    }
+
+    fn now_playing<'a>(&'a self, track: &'a Track) -> Data<'a, Submission> {
+        Box::pin(self.client.update_now_playing_submission(track))
+    }
+
+    fn scrobble<'a>(&'a self, tracks: &'a [Track]) -> Data<'a, Vec<Submission>> {
+        Box::pin(self.client.scrobble_submission(tracks))
+    }
+
+    fn recent(&self, from: u64, to: u64) -> Option<Data<'_, Vec<Track>>> {
+        let params = vec![
+            ("user".to_owned(), self.user.clone()?),
+            ("from".to_owned(), from.to_string()),
+            ("to".to_owned(), to.to_string()),
+            ("limit".to_owned(), RECENT_TRACKS_LIMIT.to_string()),
+        ];
+        Some(Box::pin(async move {
+            let res = self.client.call_raw("user.getRecentTracks", params, false).await?;
+            Ok(recent_tracks(&res))
+        }))
+    }
+}
+
+/// Extracts tracks from `user.getRecentTracks` response, skipping now playing one
+fn recent_tracks(res: &Value) -> Vec<Track> {
+    let tracks = match res["recenttracks"]["track"] {
+        Value::Array(ref items) => items.iter().collect(),
+        ref item @ Value::Object(_) => vec![item],
+        _ => Vec::new(),
+    };
+    tracks
+        .into_iter()
+        .filter_map(|t| {
+            let artist = t["artist"]["#text"].as_str().or_else(|| t["artist"]["name"].as_str())?;
+            let mut track = Track::new(t["name"].as_str()?, artist, 0);
+            track.timestamp_utc = Some(t["date"]["uts"].as_str()?.parse().ok()?);
+            Some(track)
+        })
+        .collect()
+}
+
+// ----------------------------------------------------------------
+
+/// ListenBrainz sink, submits listens with a user token
+///
+/// Token can be found at https://listenbrainz.org/profile/.
+/// Check https://listenbrainz.readthedocs.io/en/latest/users/api/core.html for API details.
+///
+/// ## Example:
+/// ```
+/// let scrobbler = Scrobbler::builder(&client)
+///     .sink(ListenBrainz::new(LISTENBRAINZ_TOKEN)?)
+///     .build()?;
+/// ```
+#[derive(Clone)]
+pub struct ListenBrainz {
+    api_url: String,
+    token: String,
+    runtime: Arc<dyn Runtime>,
+}
+
+impl ListenBrainz {
+    /// Constructs sink for given user token, it runs on `runtime::default()` runtime
+    pub fn new(token: &str) -> Result<ListenBrainz> {
+        Ok(ListenBrainz {
+            api_url: LISTENBRAINZ_API_URL.to_owned(),
+            token: token.to_owned(),
+            runtime: runtime::default()?,
+        })
+    }
+
+    /// Updates base API url, for self-hosted servers, like `https://lb.example.org/api`.
+    ///
+    /// API paths are resolved relative to it, so trailing slash is added if it's missing.
+    pub fn api_url(mut self, url: &str) -> ListenBrainz {
+        self.api_url = url.to_owned();
+        if !self.api_url.ends_with('/') {
+            self.api_url.push('/');
+        }
+        self
+    }
+
+    /// Sets async runtime, that is used to connect
+    pub fn runtime<R: Runtime + 'static>(mut self, runtime: R) -> ListenBrainz {
+        self.runtime = Arc::new(runtime);
+        self
+    }
+
+    /// Calls `submit-listens` with given listen type and listens
+    async fn submit(&self, listen_type: &str, listens: Vec<Value>) -> Result<()> {
+        let invalid = |e: ParseError| {
+            Error::io(IoErrorKind::InvalidInput, format!("Invalid ListenBrainz API url {}: {}", self.api_url, e))
+        };
+        let url = self.api_url.parse::<Url>().and_then(|url| url.join("1/submit-listens")).map_err(invalid)?;
+        let count = listens.len();
+        let body = json!({ "listen_type": listen_type, "payload": listens }).to_string();
+
+        let token = format!("Token {}", self.token);
+        let request = http::post_json(&url, Some(&token), &body)?;
+        debug!("-> POST {} ({} listens)", url, count);
+        let addr = http::resolve(&url).await?;
+        let reply = http::exchange(&*self.runtime, addr, &url, &request).await?;
+        debug!("<- {} {}", reply.status, url);
+        trace!("<- {}", reply.body);
+
+        // errors come with `{"code": 400, "error": "..."}` bodies, 401 means the token is not valid
+        if reply.status < 200 || reply.status >= 300 {
+            return Err(Error::http(reply.status, reply.headers, &reply.body));
+        }
+        Ok(())
+    }
+}
+
+/// Builds listen payload, `listened_at` is only set for finished listens
+fn listen(track: &Track, listened_at: Option<u32>) -> Value {
+    let mut info = json!({
+        "duration_ms": track.duration_sec as u64 * 1000,
+        "submission_client": "first-fm",
+        "submission_client_version": env!("CARGO_PKG_VERSION"),
+    });
+    if let Some(number) = track.track_number {
+        info["tracknumber"] = json!(number);
+    }
+    if let Some(ref mbid) = track.mbid {
+        info["recording_mbid"] = json!(mbid);
+    }
+
+    let mut metadata = json!({
+        "artist_name": track.artist,
+        "track_name": track.name,
+        "additional_info": info,
+    });
+    if let Some(ref album) = track.album {
+        metadata["release_name"] = json!(album);
+    }
+
+    let mut listen = json!({ "track_metadata": metadata });
+    if let Some(listened_at) = listened_at {
+        listen["listened_at"] = json!(listened_at);
+    }
+    listen
+}
+
+impl ScrobbleSink for ListenBrainz {
+    fn name(&self) -> &str {
+        "listenbrainz"
+    }
+
+    fn max_batch(&self) -> usize {
+        LISTENBRAINZ_MAX_BATCH
+    }
+
+    fn runtime(&self) -> Arc<dyn Runtime> {
+        self.runtime.clone()
+    }
+
+    fn now_playing<'a>(&'a self, track: &'a Track) -> Data<'a, Submission> {
+        Box::pin(async move {
+            self.submit("playing_now", vec![listen(track, None)]).await?;
+            Ok(Submission::default())
+        })
+    }
+
+    fn scrobble<'a>(&'a self, tracks: &'a [Track]) -> Data<'a, Vec<Submission>> {
+        Box::pin(async move {
+            let mut listens = Vec::with_capacity(tracks.len());
+            for track in tracks {
+                let listened_at = track.timestamp_utc.ok_or_else(|| {
+                    Error::io(IoErrorKind::InvalidInput, "no scrobble timestamp set")
+                })?;
+                listens.push(listen(track, Some(listened_at)));
+            }
+            let listen_type = if listens.len() == 1 { "single" } else { "import" };
+            self.submit(listen_type, listens).await?;
+            Ok(vec![Submission::default(); tracks.len()])
+        })
+    }
+}
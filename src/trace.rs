@@ -0,0 +1,104 @@
+use std::time::Duration;
+
+use url::Url;
+
+use crate::utils::Error;
+
+// ----------------------------------------------------------------
+
+/// Query parameters that are never exposed to instrumentation hooks and logs,
+/// `a` is the auth token of Audioscrobbler handshake
+pub static REDACTED_PARAMS: &[&str] = &["api_key", "sk", "api_sig", "password", "token", "a"];
+
+/// API methods, whose response bodies are never exposed to instrumentation hooks and logs.
+/// `auth.*` responses carry session keys.
+pub static REDACTED_METHODS: &[&str] = &["auth."];
+
+/// Returns url string with secret query parameter values replaced by `***`
+pub fn redact(url: &Url) -> String {
+    let pairs: Vec<(String, String)> = url.query_pairs()
+        .map(|(k, v)| {
+            let v = if REDACTED_PARAMS.contains(&k.as_ref()) {
+                "***".to_owned()
+            } else {
+                v.into_owned()
+            };
+            (k.into_owned(), v)
+        })
+        .collect();
+
+    let mut redacted = url.clone();
+    redacted.set_query(None);
+    if !pairs.is_empty() {
+        redacted.query_pairs_mut().extend_pairs(pairs.iter());
+    }
+    redacted.into()
+}
+
+/// Returns response body of given API method as it can be exposed,
+/// bodies of methods listed in `REDACTED_METHODS` are replaced by `***`
+pub fn redact_body<'a>(method: Option<&str>, body: &'a str) -> &'a str {
+    let secret = method.map_or(false, |m| REDACTED_METHODS.iter().any(|r| m.starts_with(r)));
+    if secret {
+        "***"
+    } else {
+        body
+    }
+}
+
+// ----------------------------------------------------------------
+
+/// Outgoing request details
+#[derive(Debug, Clone)]
+pub struct RequestInfo {
+    /// API method name, if known
+    pub method: Option<String>,
+    /// Request url with secrets redacted
+    pub url: String,
+    /// Whether request is sent as (signed) POST
+    pub is_post: bool,
+}
+
+impl RequestInfo {
+    /// Constructs request details from (unredacted) request url
+    pub fn new(url: &Url, is_post: bool) -> RequestInfo {
+        RequestInfo {
+            method: url.query_pairs()
+                .find(|(k, _)| k == "method")
+                .map(|(_, v)| v.into_owned()),
+            url: redact(url),
+            is_post,
+        }
+    }
+}
+
+/// Received response details
+#[derive(Debug)]
+pub struct ResponseInfo<'a> {
+    /// HTTP status code
+    pub status: u16,
+    /// Time passed since the request was started
+    pub elapsed: Duration,
+    /// Raw response body, `***` for `auth.*` methods (see `redact_body()`)
+    pub body: &'a str,
+}
+
+// ----------------------------------------------------------------
+
+/// Client instrumentation hooks
+///
+/// All callbacks are called from the task that runs the request,
+/// so they shouldn't block. Request urls and bodies of auth responses are already redacted.
+///
+/// Independently of hooks, client logs the same events using `log` crate
+/// (`debug` for requests and responses, `warn` for errors).
+pub trait Instrument: Send + Sync {
+    /// Called right before the request is sent
+    fn on_request(&self, _request: &RequestInfo) {}
+
+    /// Called when response is received, before it's decoded
+    fn on_response(&self, _request: &RequestInfo, _response: &ResponseInfo) {}
+
+    /// Called when request failed on transport level
+    fn on_error(&self, _request: &RequestInfo, _error: &Error, _elapsed: Duration) {}
+}
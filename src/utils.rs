@@ -4,7 +4,8 @@ use std::error::Error as StdError;
 use std::io::{Error as IoError, ErrorKind as IoErrorKind};
 use std::convert::From;
 
-use futures::future::Future;
+use std::future::Future;
+use std::pin::Pin;
 
 use native_tls::Error as TlsError;
 
@@ -15,8 +16,10 @@ use lastfm::error::Error as LastfmError;
 /// Common result type for sync client operations
 pub type Result<T> = StdResult<T, Error>;
 
-/// Future type for last.fm data types returned in responses
-pub type Data<'de, T> = Box<Future<Item = T, Error = Error> + Send + 'de>;
+/// Boxed future type for last.fm data types returned in responses.
+///
+/// Client methods are `async`, this type is handy to store their futures or return them from traits.
+pub type Data<'de, T> = Pin<Box<dyn Future<Output = Result<T>> + Send + 'de>>;
 
 // ----------------------------------------------------------------
 
@@ -30,14 +33,45 @@ pub enum Error {
     /// Errors returned by TLS layer
     Tls(TlsError),
     /// last.fm service and parsing errors
-    Lastfm(LastfmError),
+    Lastfm {
+        /// API error code (`{"error": N, ...}`), if the error came from an error payload
+        code: Option<u32>,
+        /// Error reported by the parser
+        inner: LastfmError,
+    },
+    /// Unsuccessful HTTP status with non-JSON body (proxy errors, 5xx pages and such)
+    Http {
+        /// HTTP status code
+        status: u16,
+        /// Response headers
+        headers: Vec<(String, String)>,
+        /// First few hundred characters of response body
+        body_snippet: String,
+    },
+    /// Response body is not a JSON document (like an HTML page)
+    NotJson {
+        /// Value of `Content-Type` header, if any
+        content_type: Option<String>,
+        /// First few hundred characters of response body
+        body_snippet: String,
+    },
+    /// Submission refused by the server as invalid (like Audioscrobbler `BADTIME`),
+    /// resubmitting it won't help
+    Rejected(String),
+}
+
+/// Max length of response body snippet stored in HTTP errors
+pub static BODY_SNIPPET_LEN: usize = 256;
+
+fn snippet(body: &str) -> String {
+    body.chars().take(BODY_SNIPPET_LEN).collect()
 }
 
 impl Error {
     /// Constructs builder error
     pub fn build<E>(inner: E) -> Error
     where
-        E: Into<Box<StdError + Send + Sync>>,
+        E: Into<Box<dyn StdError + Send + Sync>>,
     {
         Error::Build(IoError::new(IoErrorKind::InvalidInput, inner))
     }
@@ -45,7 +79,7 @@ impl Error {
     /// Constructs I/O error
     pub fn io<E>(kind: IoErrorKind, inner: E) -> Error
     where
-        E: Into<Box<StdError + Send + Sync>>,
+        E: Into<Box<dyn StdError + Send + Sync>>,
     {
         Error::Io(IoError::new(kind, inner))
     }
@@ -55,9 +89,44 @@ impl Error {
         Error::Tls(inner)
     }
 
-    /// Constructs last.fm API/parse error
+    /// Constructs last.fm parse error
     pub fn lastfm(inner: LastfmError) -> Error {
-        Error::Lastfm(inner)
+        Error::Lastfm { code: None, inner: inner }
+    }
+
+    /// Constructs last.fm API error with given error code
+    pub fn api(code: u32, inner: LastfmError) -> Error {
+        Error::Lastfm { code: Some(code), inner: inner }
+    }
+
+    /// Returns last.fm API error code, if it's an API error
+    pub fn api_code(&self) -> Option<u32> {
+        match *self {
+            Error::Lastfm { code, .. } => code,
+            _ => None,
+        }
+    }
+
+    /// Constructs HTTP status error, body is truncated to a snippet
+    pub fn http(status: u16, headers: Vec<(String, String)>, body: &str) -> Error {
+        Error::Http {
+            status: status,
+            headers: headers,
+            body_snippet: snippet(body),
+        }
+    }
+
+    /// Constructs non-JSON response error, body is truncated to a snippet
+    pub fn not_json(content_type: Option<String>, body: &str) -> Error {
+        Error::NotJson {
+            content_type: content_type,
+            body_snippet: snippet(body),
+        }
+    }
+
+    /// Constructs rejected submission error with server's reason
+    pub fn rejected<S: Into<String>>(reason: S) -> Error {
+        Error::Rejected(reason.into())
     }
 }
 
@@ -89,27 +158,31 @@ impl Display for Error {
             Error::Build(ref inn) => write!(f, "Failed to build the client: {}", inn),
             Error::Io(ref inn) => write!(f, "I/O error: {}", inn),
             Error::Tls(ref inn) => write!(f, "HTTPS error: {}", inn),
-            Error::Lastfm(ref inn) => write!(f, "Lastfm error: {}", inn),
+            Error::Lastfm { ref inner, .. } => write!(f, "Lastfm error: {}", inner),
+            Error::Http { status, ref body_snippet, .. } => {
+                write!(f, "HTTP error: status {}, body: {}", status, body_snippet)
+            }
+            Error::NotJson { ref content_type, ref body_snippet } => {
+                write!(
+                    f,
+                    "Response is not JSON (content type: {}): {}",
+                    content_type.as_deref().unwrap_or("unknown"),
+                    body_snippet
+                )
+            }
+            Error::Rejected(ref reason) => write!(f, "Submission rejected: {}", reason),
         }
     }
 }
 
 impl StdError for Error {
-    fn description(&self) -> &str {
-        match *self {
-            Error::Build(ref inn) => inn.description(),
-            Error::Io(ref inn) => inn.description(),
-            Error::Tls(ref inn) => inn.description(),
-            Error::Lastfm(ref inn) => inn.description(),
-        }
-    }
-
-    fn cause(&self) -> Option<&StdError> {
+    fn source(&self) -> Option<&(dyn StdError + 'static)> {
         match *self {
             Error::Build(ref inn) => Some(inn),
             Error::Io(ref inn) => Some(inn),
             Error::Tls(ref inn) => Some(inn),
-            Error::Lastfm(ref inn) => Some(inn),
+            Error::Lastfm { ref inner, .. } => Some(inner),
+            Error::Http { .. } | Error::NotJson { .. } | Error::Rejected(_) => None,
         }
     }
 }
@@ -0,0 +1,314 @@
+use std::io::ErrorKind as IoErrorKind;
+
+use serde_json::Value;
+
+use lastfm::track::{UpdateNowPlaying, Scrobble};
+
+use crate::client::{Client, decode};
+use crate::raw::RawRequest;
+use crate::lookup::Subject;
+use crate::scrobbler::Track;
+use crate::utils::{Error, Result};
+
+// ----------------------------------------------------------------
+
+/// Max number of tracks last.fm accepts in a single `track.scrobble` call
+pub static MAX_SCROBBLE_BATCH: usize = 50;
+
+// ----------------------------------------------------------------
+
+/// Track tags corrected by last.fm, only corrected ones are set
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Correction {
+    /// Corrected artist name
+    pub artist: Option<String>,
+    /// Corrected track title
+    pub name: Option<String>,
+    /// Corrected album title
+    pub album: Option<String>,
+    /// Corrected album artist name
+    pub album_artist: Option<String>,
+}
+
+impl Correction {
+    /// Returns `true` if nothing was corrected
+    pub fn is_empty(&self) -> bool {
+        self.artist.is_none() && self.name.is_none() && self.album.is_none() && self.album_artist.is_none()
+    }
+
+    /// Replaces track tags with corrected ones
+    pub fn apply(&self, track: &mut Track) {
+        if let Some(ref artist) = self.artist {
+            track.artist = artist.clone();
+        }
+        if let Some(ref name) = self.name {
+            track.name = name.clone();
+        }
+        if let Some(ref album) = self.album {
+            track.album = Some(album.clone());
+        }
+        if let Some(ref album_artist) = self.album_artist {
+            track.album_artist = Some(album_artist.clone());
+        }
+    }
+}
+
+/// last.fm response to a single track of `track.scrobble` or `track.updateNowPlaying`
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Submission {
+    /// Corrections last.fm made to track tags, if any
+    pub correction: Option<Correction>,
+    /// Reason the track was ignored, if it was
+    pub ignored: Option<String>,
+}
+
+impl Submission {
+    /// Parses `{"artist": {"corrected": "1", "#text": "..."}, "ignoredMessage": {...}, ...}` object
+    pub(crate) fn from_json(value: &Value) -> Submission {
+        let corrected = |key: &str| {
+            let field = &value[key];
+            let flag = code(&field["corrected"]).map_or(false, |c| c != "0");
+            field["#text"].as_str().filter(|_| flag).map(|text| text.to_owned())
+        };
+        let correction = Correction {
+            artist: corrected("artist"),
+            name: corrected("track"),
+            album: corrected("album"),
+            album_artist: corrected("albumArtist"),
+        };
+
+        let ignored = &value["ignoredMessage"];
+        let ignored = match code(&ignored["code"]).as_deref() {
+            Some(code) if code != "0" => {
+                let message = ignored["#text"].as_str().filter(|t| !t.is_empty());
+                Some(message.unwrap_or(code).to_owned())
+            }
+            _ => None,
+        };
+
+        Submission {
+            correction: Some(correction).filter(|c| !c.is_empty()),
+            ignored,
+        }
+    }
+}
+
+/// Reads a numeric code, GNU FM servers send numbers where last.fm sends strings
+fn code(value: &Value) -> Option<String> {
+    match *value {
+        Value::String(ref s) => Some(s.clone()),
+        Value::Number(ref n) => Some(n.to_string()),
+        _ => None,
+    }
+}
+
+// ----------------------------------------------------------------
+
+/// High-level `write` API.
+///
+/// All these methods require the client to be authenticated (see `mobile_auth()`
+/// and `init_desktop_auth()`) and fail right away if it isn't.
+impl Client {
+    /// Calls `track.love`
+    pub async fn love_track(&self, artist: &str, track: &str) -> Result<()> {
+        self.write("track.love", vec![("artist", artist), ("track", track)]).await
+    }
+
+    /// Calls `track.unlove`
+    pub async fn unlove_track(&self, artist: &str, track: &str) -> Result<()> {
+        self.write("track.unlove", vec![("artist", artist), ("track", track)]).await
+    }
+
+    /// Calls `artist.addTags`, `album.addTags` or `track.addTags` depending on the subject.
+    ///
+    /// last.fm accepts at most 10 tags per call.
+    pub async fn add_tags(&self, subject: &Subject, tags: &[&str]) -> Result<()> {
+        let tags = tags.join(",");
+        let (family, mut params) = tag_params(subject)?;
+        params.push(("tags", tags.as_str()));
+        self.write(&format!("{}.addTags", family), params).await
+    }
+
+    /// Calls `artist.removeTag`, `album.removeTag` or `track.removeTag` depending on the subject
+    pub async fn remove_tag(&self, subject: &Subject, tag: &str) -> Result<()> {
+        let (family, mut params) = tag_params(subject)?;
+        params.push(("tag", tag));
+        self.write(&format!("{}.removeTag", family), params).await
+    }
+
+    /// Calls `track.updateNowPlaying` for given track.
+    ///
+    /// Fails for services with legacy submission protocol, use `update_now_playing_submission()` there.
+    pub async fn update_now_playing<'rsp>(
+        &self,
+        storage: &'rsp mut String,
+        track: &Track,
+    ) -> Result<UpdateNowPlaying<'rsp>> {
+        self.ensure_not_legacy("track.updateNowPlaying")?;
+        let body = self.now_playing_request(track)?.send().await?;
+        decode(storage, body)
+    }
+
+    /// Calls `track.scrobble` for given batch of tracks (at most 50).
+    ///
+    /// Every track has to have a play start timestamp set.
+    /// Fails for services with legacy submission protocol, use `scrobble_submission()` there.
+    pub async fn scrobble<'rsp>(&self, storage: &'rsp mut String, tracks: &[Track]) -> Result<Scrobble<'rsp>> {
+        self.ensure_not_legacy("track.scrobble")?;
+        let body = self.scrobble_request(tracks)?.send().await?;
+        decode(storage, body)
+    }
+
+    /// Calls `track.updateNowPlaying` for given track and reports how last.fm corrected it.
+    ///
+    /// Services with legacy submission protocol don't report corrections.
+    pub async fn update_now_playing_submission(&self, track: &Track) -> Result<Submission> {
+        if let Some(legacy) = self.legacy() {
+            self.ensure_authenticated()?;
+            self.legacy_now_playing(legacy, track).await?;
+            return Ok(Submission::default());
+        }
+        let res = self.now_playing_request(track)?.send_json().await?;
+        Ok(Submission::from_json(&res["nowplaying"]))
+    }
+
+    /// Calls `track.scrobble` for given batch of tracks (at most 50) and reports
+    /// how last.fm corrected each of them, in the same order
+    pub async fn scrobble_submission(&self, tracks: &[Track]) -> Result<Vec<Submission>> {
+        if let Some(legacy) = self.legacy() {
+            self.ensure_authenticated()?;
+            if tracks.is_empty() || tracks.len() > MAX_SCROBBLE_BATCH {
+                return Err(Error::io(
+                    IoErrorKind::InvalidInput,
+                    "Scrobble batch should contain from 1 to 50 tracks",
+                ));
+            }
+            self.legacy_scrobble(legacy, tracks).await?;
+            return Ok(vec![Submission::default(); tracks.len()]);
+        }
+        let res = self.scrobble_request(tracks)?.send_json().await?;
+        // single scrobble is returned as an object rather than a list
+        let submissions = match res["scrobbles"]["scrobble"] {
+            Value::Array(ref items) => items.iter().map(Submission::from_json).collect(),
+            ref item @ Value::Object(_) => vec![Submission::from_json(item)],
+            _ => Vec::new(),
+        };
+        Ok(submissions)
+    }
+
+    pub(crate) fn now_playing_request(&self, track: &Track) -> Result<RawRequest> {
+        self.ensure_authenticated()?;
+
+        let mut params = vec![
+            ("artist".to_owned(), track.artist.clone()),
+            ("track".to_owned(), track.name.clone()),
+            ("duration".to_owned(), track.duration_sec.to_string()),
+        ];
+        if let Some(ref album) = track.album {
+            params.push(("album".to_owned(), album.clone()));
+        }
+        if let Some(ref album_artist) = track.album_artist {
+            params.push(("albumArtist".to_owned(), album_artist.clone()));
+        }
+        if let Some(number) = track.track_number {
+            params.push(("trackNumber".to_owned(), number.to_string()));
+        }
+        if let Some(ref mbid) = track.mbid {
+            params.push(("mbid".to_owned(), mbid.clone()));
+        }
+        if let Some(ref context) = track.context {
+            params.push(("context".to_owned(), context.clone()));
+        }
+
+        Ok(self.raw_request("track.updateNowPlaying").params(params).signed(true))
+    }
+
+    pub(crate) fn scrobble_request(&self, tracks: &[Track]) -> Result<RawRequest> {
+        self.ensure_authenticated()?;
+        if tracks.is_empty() || tracks.len() > MAX_SCROBBLE_BATCH {
+            return Err(Error::io(
+                IoErrorKind::InvalidInput,
+                "Scrobble batch should contain from 1 to 50 tracks",
+            ));
+        }
+
+        let mut params = Vec::new();
+        for (i, track) in tracks.iter().enumerate() {
+            let timestamp = track.timestamp_utc.ok_or_else(|| {
+                Error::io(IoErrorKind::InvalidInput, "no scrobble timestamp set")
+            })?;
+
+            params.push((format!("artist[{}]", i), track.artist.clone()));
+            params.push((format!("track[{}]", i), track.name.clone()));
+            params.push((format!("timestamp[{}]", i), timestamp.to_string()));
+            params.push((format!("duration[{}]", i), track.duration_sec.to_string()));
+            if let Some(ref album) = track.album {
+                params.push((format!("album[{}]", i), album.clone()));
+            }
+            if let Some(ref album_artist) = track.album_artist {
+                params.push((format!("albumArtist[{}]", i), album_artist.clone()));
+            }
+            if let Some(number) = track.track_number {
+                params.push((format!("trackNumber[{}]", i), number.to_string()));
+            }
+            if let Some(ref mbid) = track.mbid {
+                params.push((format!("mbid[{}]", i), mbid.clone()));
+            }
+            if let Some(chosen) = track.chosen_by_user {
+                params.push((format!("chosenByUser[{}]", i), (chosen as u8).to_string()));
+            }
+            if let Some(ref context) = track.context {
+                params.push((format!("context[{}]", i), context.clone()));
+            }
+            if let Some(ref stream_id) = track.stream_id {
+                params.push((format!("streamId[{}]", i), stream_id.clone()));
+            }
+        }
+
+        Ok(self.raw_request("track.scrobble").params(params).signed(true))
+    }
+
+    async fn write(&self, method: &str, params: Vec<(&str, &str)>) -> Result<()> {
+        self.ensure_authenticated()?;
+        self.raw_request(method).params(params).signed(true).send_json().await?;
+        Ok(())
+    }
+
+    fn ensure_authenticated(&self) -> Result<()> {
+        if self.is_authenticated() {
+            Ok(())
+        } else {
+            Err(Error::io(
+                IoErrorKind::PermissionDenied,
+                "Client is not authenticated, can't call write methods",
+            ))
+        }
+    }
+
+    /// Typed 2.0 API responses can't be produced from legacy protocol replies
+    fn ensure_not_legacy(&self, method: &str) -> Result<()> {
+        match self.legacy() {
+            Some(_) => Err(Error::io(
+                IoErrorKind::InvalidInput,
+                format!("Can't call {} with legacy submission protocol, use *_submission() methods", method),
+            )),
+            None => Ok(()),
+        }
+    }
+}
+
+fn tag_params(subject: &Subject) -> Result<(&'static str, Vec<(&str, &str)>)> {
+    match *subject {
+        Subject::Artist(ref artist) => Ok(("artist", vec![("artist", artist.as_str())])),
+        Subject::Album(ref artist, ref album) => {
+            Ok(("album", vec![("artist", artist.as_str()), ("album", album.as_str())]))
+        }
+        Subject::Track(ref artist, ref track) => {
+            Ok(("track", vec![("artist", artist.as_str()), ("track", track.as_str())]))
+        }
+        Subject::Mbid(_) => Err(Error::io(
+            IoErrorKind::InvalidInput,
+            "Tags can only be changed by artist, album or track name",
+        )),
+    }
+}